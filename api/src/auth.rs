@@ -40,9 +40,13 @@ impl ApiKey {
     }
 
     pub fn new_random() -> Self {
+        Self::new_random_with(&mut rand::thread_rng())
+    }
+
+    /// Generate a key from `rng`, so tests can use a seeded one
+    pub fn new_random_with(rng: &mut impl Rng) -> Self {
         Self(
-            rand::thread_rng()
-                .sample_iter(&rand::distributions::Alphanumeric)
+            rng.sample_iter(&rand::distributions::Alphanumeric)
                 .take(16)
                 .map(char::from)
                 .collect::<String>(),
@@ -321,6 +325,8 @@ impl UserDirectory {
 
 #[cfg(test)]
 pub mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
     use crate::auth::ApiKey;
 
     #[test]
@@ -328,4 +334,18 @@ pub mod tests {
         let api_key = ApiKey::from_authorization_header("Basic bXlfYXBpX2tleTo=").unwrap();
         assert_eq!(api_key, ApiKey("my_api_key".to_string()))
     }
+
+    #[test]
+    pub fn test_api_key_random() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let first = ApiKey::new_random_with(&mut rng);
+        let second = ApiKey::new_random_with(&mut rng);
+
+        assert_eq!(first.0.len(), 16);
+        assert!(first.0.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(first, second);
+
+        let mut rng = StdRng::seed_from_u64(42);
+        assert_eq!(ApiKey::new_random_with(&mut rng), first);
+    }
 }
@@ -44,6 +44,7 @@ impl Factory for ShuttleFactory {
         let request = Request::new(DatabaseRequest {
             project_name: self.project_name.to_string(),
             db_type: Some(db_type),
+            ..Default::default()
         });
 
         let response = self
@@ -57,7 +58,7 @@ impl Factory for ShuttleFactory {
         let conn_str = info.connection_string_private();
         self.info = Some(info);
 
-        debug!("giving a sql connection string: {}", conn_str);
+        debug!("giving a sql connection string for {}", self.project_name);
         Ok(conn_str)
     }
 }
@@ -1,3 +1,29 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// Usernames RDS keeps for itself, whatever the engine
+const RESERVED_MASTER_USERNAMES: [&str; 2] = ["rdsadmin", "admin"];
+
+/// Reserved words of Postgres which RDS will not take as a master username
+const RESERVED_POSTGRES_WORDS: &[&str] = &[
+    "all", "analyse", "analyze", "and", "any", "array", "as", "asc", "both", "case", "cast",
+    "check", "collate", "column", "constraint", "create", "default", "desc", "distinct", "do",
+    "else", "end", "except", "false", "for", "foreign", "from", "grant", "group", "having", "in",
+    "into", "leading", "limit", "not", "null", "offset", "on", "only", "or", "order", "postgres",
+    "primary", "public", "references", "returning", "select", "some", "table", "then", "to",
+    "true", "union", "unique", "user", "using", "when", "where", "window", "with",
+];
+
+/// Reserved words of MySQL and MariaDB which RDS will not take as a master username
+const RESERVED_MYSQL_WORDS: &[&str] = &[
+    "add", "all", "alter", "and", "as", "asc", "before", "between", "by", "case", "change",
+    "check", "column", "create", "cross", "database", "databases", "default", "delete", "desc",
+    "distinct", "drop", "else", "exists", "false", "for", "from", "grant", "group", "having",
+    "in", "index", "insert", "into", "is", "join", "key", "keys", "kill", "like", "limit", "lock",
+    "mysql", "not", "null", "on", "or", "order", "primary", "rename", "select", "set", "show",
+    "table", "then", "to", "true", "union", "unique", "update", "use", "using", "where", "with",
+];
+
 pub enum Type {
     AwsRds(AwsRdsEngine),
     Shared,
@@ -8,3 +34,162 @@ pub enum AwsRdsEngine {
     MySql,
     MariaDB,
 }
+
+impl AwsRdsEngine {
+    /// Longest master username RDS accepts for the engine
+    pub fn max_master_username_length(&self) -> usize {
+        match self {
+            AwsRdsEngine::Postgres => 63,
+            AwsRdsEngine::MySql | AwsRdsEngine::MariaDB => 16,
+        }
+    }
+
+    fn reserved_words(&self) -> &'static [&'static str] {
+        match self {
+            AwsRdsEngine::Postgres => RESERVED_POSTGRES_WORDS,
+            AwsRdsEngine::MySql | AwsRdsEngine::MariaDB => RESERVED_MYSQL_WORDS,
+        }
+    }
+}
+
+impl Display for AwsRdsEngine {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AwsRdsEngine::Postgres => write!(f, "postgres"),
+            AwsRdsEngine::MySql => write!(f, "mysql"),
+            AwsRdsEngine::MariaDB => write!(f, "mariadb"),
+        }
+    }
+}
+
+/// Derive the master username of a project's RDS instance from its project name.
+/// Dashes are dropped, the name is lowercased and cut to the length `engine` accepts, so
+/// `my-project` becomes `myproject`. RDS only allows letters and digits, starting with a letter,
+/// and no reserved words of the engine. Like project names, the name cannot start with a dash.
+pub fn aws_rds_master_username(
+    project_name: &str,
+    engine: &AwsRdsEngine,
+) -> Result<String, MasterUsernameError> {
+    if project_name.starts_with('-') {
+        return Err(MasterUsernameError::InvalidProjectName(
+            project_name.to_string(),
+        ));
+    }
+
+    let username: String = project_name
+        .chars()
+        .filter(|c| *c != '-')
+        .map(|c| c.to_ascii_lowercase())
+        .take(engine.max_master_username_length())
+        .collect();
+
+    let is_valid = username.starts_with(|c: char| c.is_ascii_alphabetic())
+        && username.chars().all(|c| c.is_ascii_alphanumeric())
+        && !RESERVED_MASTER_USERNAMES.contains(&username.as_str());
+    if !is_valid {
+        return Err(MasterUsernameError::InvalidProjectName(
+            project_name.to_string(),
+        ));
+    }
+
+    if engine.reserved_words().contains(&username.as_str()) {
+        return Err(MasterUsernameError::ReservedWord {
+            username,
+            engine: engine.to_string(),
+        });
+    }
+
+    Ok(username)
+}
+
+#[derive(Debug)]
+pub enum MasterUsernameError {
+    InvalidProjectName(String),
+    ReservedWord { username: String, engine: String },
+}
+
+impl Display for MasterUsernameError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MasterUsernameError::InvalidProjectName(name) => write!(
+                f,
+                "cannot make an RDS master username from project name `{}`: it must start with a letter, only contain letters, digits and `-`, and not be a name reserved by RDS",
+                name
+            ),
+            MasterUsernameError::ReservedWord { username, engine } => write!(
+                f,
+                "cannot use `{}` as the RDS master username: it is a reserved word of {}, pick another project name",
+                username, engine
+            ),
+        }
+    }
+}
+
+impl Error for MasterUsernameError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn master_username() {
+        for (project_name, username) in [
+            ("my-project", "myproject"),
+            ("Upper", "upper"),
+            ("a1", "a1"),
+            ("a-very-long-project-name", "averylongproject"),
+        ] {
+            assert_eq!(
+                aws_rds_master_username(project_name, &AwsRdsEngine::MySql).unwrap(),
+                username
+            );
+        }
+
+        for project_name in ["", "1project", "-dash", "under_score", "rds-admin"] {
+            for engine in [AwsRdsEngine::Postgres, AwsRdsEngine::MySql] {
+                assert!(
+                    aws_rds_master_username(project_name, &engine).is_err(),
+                    "{project_name:?} was ok for {engine}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn master_username_length() {
+        let project_name = "a-project-name-which-is-well-over-thirty-two-characters";
+
+        let mysql = aws_rds_master_username(project_name, &AwsRdsEngine::MySql).unwrap();
+        assert_eq!(mysql, "aprojectnamewhic");
+        assert_eq!(
+            aws_rds_master_username(project_name, &AwsRdsEngine::MariaDB).unwrap(),
+            mysql
+        );
+
+        // Postgres takes much longer usernames
+        let postgres = aws_rds_master_username(project_name, &AwsRdsEngine::Postgres).unwrap();
+        assert_eq!(postgres, "aprojectnamewhichiswelloverthirtytwocharacters");
+    }
+
+    #[test]
+    fn master_username_reserved_word() {
+        let error = aws_rds_master_username("data-base", &AwsRdsEngine::MySql).unwrap_err();
+        assert!(matches!(
+            error,
+            MasterUsernameError::ReservedWord { ref username, ref engine }
+                if username == "database" && engine == "mysql"
+        ));
+        assert!(error.to_string().contains("reserved word of mysql"));
+
+        // Reserved words differ between engines
+        assert_eq!(
+            aws_rds_master_username("data-base", &AwsRdsEngine::Postgres).unwrap(),
+            "database"
+        );
+        assert!(aws_rds_master_username("user", &AwsRdsEngine::Postgres).is_err());
+        assert_eq!(
+            aws_rds_master_username("user", &AwsRdsEngine::MySql).unwrap(),
+            "user"
+        );
+    }
+}
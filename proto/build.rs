@@ -1,5 +1,10 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("../proto/provisioner.proto")?;
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR")?);
+
+    // The provisioner serves the descriptors for gRPC reflection
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("provisioner_descriptor.bin"))
+        .compile(&["../proto/provisioner.proto"], &["../proto"])?;
 
     Ok(())
 }
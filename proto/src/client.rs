@@ -0,0 +1,311 @@
+//! A provisioner client which retries calls that failed for reasons which go away on their own,
+//! like the provisioner restarting, so every consumer does not have to get backoff right itself
+
+use std::{future::Future, iter, time::Duration};
+
+use tokio::time::sleep;
+use tonic::{transport::Channel, Code, Response, Status};
+
+use crate::provisioner::{
+    provisioner_client::ProvisionerClient, DatabaseRequest, DatabaseResponse,
+    DeleteDatabaseResponse, DescribeDatabaseResponse, ListDatabasesRequest, ListDatabasesResponse,
+    ProvisionDatabasesRequest, ProvisionDatabasesResponse, SupportedEnginesRequest,
+    SupportedEnginesResponse,
+};
+
+/// How often and how patiently to retry a call
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts a call gets, the first one included, before its error is returned
+    pub attempts: usize,
+
+    /// Delay before the first retry. It doubles after every retry
+    pub initial_backoff: Duration,
+
+    /// Longest delay between retries
+    pub max_backoff: Duration,
+
+    /// Retry calls which failed with `DeadlineExceeded`, which happens by default. The provisioner
+    /// may have done the work of such a call anyway, so turn this off when calls are made which
+    /// are not fine to repeat. A provision with `recreate` set is one, as it would drop the
+    /// database again
+    pub retry_deadline_exceeded: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 4,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(5),
+            retry_deadline_exceeded: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Whether a call which failed with `code` can succeed when made again. That is `Unavailable`,
+    /// and `DeadlineExceeded` unless the policy turns it off. Everything else, like
+    /// `InvalidArgument` or `ResourceExhausted`, fails the same way every time
+    pub fn is_retryable(&self, code: Code) -> bool {
+        match code {
+            Code::Unavailable => true,
+            Code::DeadlineExceeded => self.retry_deadline_exceeded,
+            _ => false,
+        }
+    }
+
+    /// Delays between attempts, doubling from `initial_backoff` up to `max_backoff`
+    fn delays(&self) -> impl Iterator<Item = Duration> {
+        let max = self.max_backoff;
+
+        iter::successors(Some(self.initial_backoff.min(max)), move |delay| {
+            Some((*delay * 2).min(max))
+        })
+        .take(self.attempts.saturating_sub(1))
+    }
+}
+
+/// Wraps a [ProvisionerClient], retrying calls which failed with a retryable code as set out by
+/// its [RetryPolicy]. These codes do not only come from the transport: the provisioner itself
+/// returns `Unavailable` for AWS and database errors which are worth retrying, and while its
+/// database is read-only. Such calls may have done part of their work before failing
+#[derive(Clone, Debug)]
+pub struct RetryingClient {
+    client: ProvisionerClient<Channel>,
+    policy: RetryPolicy,
+}
+
+impl RetryingClient {
+    pub fn new(client: ProvisionerClient<Channel>) -> Self {
+        Self {
+            client,
+            policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Retry calls as set out by `policy` instead of the default policy
+    pub fn with_policy(self, policy: RetryPolicy) -> Self {
+        Self { policy, ..self }
+    }
+
+    /// The client calls are made with
+    pub fn into_inner(self) -> ProvisionerClient<Channel> {
+        self.client
+    }
+
+    pub async fn provision_database(
+        &self,
+        request: DatabaseRequest,
+    ) -> Result<DatabaseResponse, Status> {
+        self.call(request, |mut client, request| async move {
+            client.provision_database(request).await
+        })
+        .await
+    }
+
+    pub async fn provision_databases(
+        &self,
+        request: ProvisionDatabasesRequest,
+    ) -> Result<ProvisionDatabasesResponse, Status> {
+        self.call(request, |mut client, request| async move {
+            client.provision_databases(request).await
+        })
+        .await
+    }
+
+    pub async fn delete_database(
+        &self,
+        request: DatabaseRequest,
+    ) -> Result<DeleteDatabaseResponse, Status> {
+        self.call(request, |mut client, request| async move {
+            client.delete_database(request).await
+        })
+        .await
+    }
+
+    pub async fn list_databases(
+        &self,
+        request: ListDatabasesRequest,
+    ) -> Result<ListDatabasesResponse, Status> {
+        self.call(request, |mut client, request| async move {
+            client.list_databases(request).await
+        })
+        .await
+    }
+
+    pub async fn describe_database(
+        &self,
+        request: DatabaseRequest,
+    ) -> Result<DescribeDatabaseResponse, Status> {
+        self.call(request, |mut client, request| async move {
+            client.describe_database(request).await
+        })
+        .await
+    }
+
+    pub async fn supported_engines(
+        &self,
+        request: SupportedEnginesRequest,
+    ) -> Result<SupportedEnginesResponse, Status> {
+        self.call(request, |mut client, request| async move {
+            client.supported_engines(request).await
+        })
+        .await
+    }
+
+    pub async fn rotate_credentials(
+        &self,
+        request: DatabaseRequest,
+    ) -> Result<DatabaseResponse, Status> {
+        self.call(request, |mut client, request| async move {
+            client.rotate_credentials(request).await
+        })
+        .await
+    }
+
+    pub async fn get_connection_info(
+        &self,
+        request: DatabaseRequest,
+    ) -> Result<DatabaseResponse, Status> {
+        self.call(request, |mut client, request| async move {
+            client.get_connection_info(request).await
+        })
+        .await
+    }
+
+    /// Make `call` with a copy of `message` until it succeeds, fails with a code which is not
+    /// retryable, or runs out of attempts
+    async fn call<M, T, F, Fut>(&self, message: M, call: F) -> Result<T, Status>
+    where
+        M: Clone,
+        F: Fn(ProvisionerClient<Channel>, M) -> Fut,
+        Fut: Future<Output = Result<Response<T>, Status>>,
+    {
+        let mut delays = self.policy.delays();
+
+        loop {
+            match call(self.client.clone(), message.clone()).await {
+                Ok(response) => return Ok(response.into_inner()),
+                Err(status) if self.policy.is_retryable(status.code()) => match delays.next() {
+                    Some(delay) => sleep(delay).await,
+                    None => return Err(status),
+                },
+                Err(status) => return Err(status),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, TcpListener};
+
+    use tonic::transport::Server;
+
+    use crate::{
+        mock::{MockProvisioner, MOCK_PASSWORD},
+        provisioner::{database_request::DbType, provisioner_server::ProvisionerServer, Shared},
+    };
+
+    use super::*;
+
+    const POLICY: RetryPolicy = RetryPolicy {
+        attempts: 3,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(5),
+        retry_deadline_exceeded: true,
+    };
+
+    /// Serve `mock` on a free port, and connect a retrying client to it
+    async fn serve(mock: MockProvisioner) -> RetryingClient {
+        let addr: SocketAddr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(ProvisionerServer::new(mock))
+                .serve(addr),
+        );
+
+        for _ in 0..50 {
+            if let Ok(client) = ProvisionerClient::connect(format!("http://{addr}")).await {
+                return RetryingClient::new(client).with_policy(POLICY);
+            }
+
+            sleep(Duration::from_millis(20)).await;
+        }
+
+        panic!("mock provisioner did not start on {addr}");
+    }
+
+    fn request() -> DatabaseRequest {
+        DatabaseRequest {
+            project_name: "retried".to_string(),
+            db_type: Some(DbType::Shared(Shared::default())),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn delays() {
+        let delays: Vec<_> = POLICY.delays().collect();
+
+        assert_eq!(delays, [1, 2].map(Duration::from_millis).to_vec());
+    }
+
+    #[tokio::test]
+    async fn retries_transient_failures() {
+        let mock = MockProvisioner::default().with_error_times(Code::Unavailable, "restarting", 2);
+        let client = serve(mock.clone()).await;
+
+        let response = client.provision_database(request()).await.unwrap();
+
+        assert_eq!(response.password, MOCK_PASSWORD);
+        assert_eq!(mock.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_attempts() {
+        let mock = MockProvisioner::default().with_error(Code::Unavailable, "restarting");
+        let client = serve(mock.clone()).await;
+
+        let status = client.provision_database(request()).await.unwrap_err();
+
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(mock.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_deadline_exceeded() {
+        let mock = MockProvisioner::default().with_error(Code::DeadlineExceeded, "too slow");
+        let client = serve(mock.clone()).await;
+
+        let status = client.provision_database(request()).await.unwrap_err();
+        assert_eq!(status.code(), Code::DeadlineExceeded);
+        assert_eq!(mock.requests().len(), 3);
+
+        // Callers making calls which are not fine to repeat can turn it off
+        let client = client.with_policy(RetryPolicy {
+            retry_deadline_exceeded: false,
+            ..POLICY
+        });
+        let status = client.provision_database(request()).await.unwrap_err();
+        assert_eq!(status.code(), Code::DeadlineExceeded);
+        assert_eq!(mock.requests().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn surfaces_permanent_failures() {
+        for code in [Code::InvalidArgument, Code::ResourceExhausted] {
+            let mock = MockProvisioner::default().with_error(code, "never going to work");
+            let client = serve(mock.clone()).await;
+
+            let status = client.provision_database(request()).await.unwrap_err();
+
+            assert_eq!(status.code(), code);
+            assert_eq!(mock.requests().len(), 1, "{code:?}");
+        }
+    }
+}
@@ -1,6 +1,12 @@
+#[cfg(any(test, feature = "client"))]
+pub mod client;
+#[cfg(any(test, feature = "test-util"))]
+pub mod mock;
+
 pub mod provisioner {
-    use std::fmt::Display;
+    use std::{fmt::Display, time::Duration};
 
+    use serde::Serialize;
     use shuttle_common::{
         database::{self, AwsRdsEngine},
         DatabaseReadyInfo,
@@ -8,9 +14,13 @@ pub mod provisioner {
 
     tonic::include_proto!("provisioner");
 
+    /// Encoded descriptors of the provisioner protos, for serving gRPC reflection
+    pub const FILE_DESCRIPTOR_SET: &[u8] =
+        tonic::include_file_descriptor_set!("provisioner_descriptor");
+
     impl From<DatabaseResponse> for DatabaseReadyInfo {
         fn from(response: DatabaseResponse) -> Self {
-            DatabaseReadyInfo::new(
+            let info = DatabaseReadyInfo::new(
                 response.engine,
                 response.username,
                 response.password,
@@ -18,16 +28,22 @@ pub mod provisioner {
                 response.port,
                 response.address_private,
                 response.address_public,
-            )
+            );
+
+            if response.sslmode.is_empty() {
+                info
+            } else {
+                info.with_sslmode(response.sslmode)
+            }
         }
     }
 
     impl From<database::Type> for database_request::DbType {
         fn from(db_type: database::Type) -> Self {
             match db_type {
-                database::Type::Shared => database_request::DbType::Shared(String::new()),
+                database::Type::Shared => database_request::DbType::Shared(Shared::default()),
                 database::Type::AwsRds(engine) => {
-                    let config = RdsConfig {};
+                    let config = RdsConfig::default();
                     let engine = match engine {
                         AwsRdsEngine::Postgres => aws_rds::Engine::Postgres(config),
                         AwsRdsEngine::MariaDB => aws_rds::Engine::Mariadb(config),
@@ -41,6 +57,14 @@ pub mod provisioner {
         }
     }
 
+    impl aws_rds::Engine {
+        pub fn config(&self) -> &RdsConfig {
+            match self {
+                Self::Mariadb(config) | Self::Mysql(config) | Self::Postgres(config) => config,
+            }
+        }
+    }
+
     impl Display for aws_rds::Engine {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             match self {
@@ -50,4 +74,141 @@ pub mod provisioner {
             }
         }
     }
+
+    /// Outcome of a provision request
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum ProvisionStatus {
+        Ready,
+        Failed,
+    }
+
+    impl Display for ProvisionStatus {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Self::Ready => write!(f, "ready"),
+                Self::Failed => write!(f, "failed"),
+            }
+        }
+    }
+
+    /// Summary of a provision request which never holds any secrets, making it safe to log
+    #[derive(Clone, Debug, Serialize)]
+    pub struct ProvisionSummary {
+        pub project: String,
+        pub engine: String,
+        pub kind: String,
+        pub endpoint: Option<String>,
+        pub status: ProvisionStatus,
+        pub duration_ms: u128,
+    }
+
+    impl ProvisionSummary {
+        /// Summarize a request and its response. A missing response means provisioning failed
+        pub fn new(
+            request: &DatabaseRequest,
+            response: Option<&DatabaseResponse>,
+            duration: Duration,
+        ) -> Self {
+            let (kind, engine) = match &request.db_type {
+                Some(database_request::DbType::Shared(_)) => ("shared", "postgres".to_string()),
+                Some(database_request::DbType::SharedRedis(_)) => {
+                    ("shared_redis", "redis".to_string())
+                }
+                Some(database_request::DbType::AwsRds(AwsRds {
+                    engine: Some(engine),
+                })) => ("aws_rds", engine.to_string()),
+                Some(database_request::DbType::AwsRds(_)) => ("aws_rds", String::new()),
+                None => ("unknown", String::new()),
+            };
+            let endpoint =
+                response.map(|response| format!("{}:{}", response.address_public, response.port));
+            let status = if response.is_some() {
+                ProvisionStatus::Ready
+            } else {
+                ProvisionStatus::Failed
+            };
+
+            Self {
+                project: request.project_name.clone(),
+                engine,
+                kind: kind.to_string(),
+                endpoint,
+                status,
+                duration_ms: duration.as_millis(),
+            }
+        }
+
+        pub fn to_json(&self) -> String {
+            serde_json::to_string(self).expect("summary to serialize")
+        }
+    }
+
+    impl Display for ProvisionSummary {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(
+                f,
+                "project={} engine={} kind={} endpoint={} status={} duration={}ms",
+                self.project,
+                self.engine,
+                self.kind,
+                self.endpoint.as_deref().unwrap_or("-"),
+                self.status,
+                self.duration_ms
+            )
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn summary_is_redacted() {
+            let request = DatabaseRequest {
+                project_name: "summary".to_string(),
+                db_type: Some(database_request::DbType::Shared(Shared::default())),
+                ..Default::default()
+            };
+            let response = DatabaseResponse {
+                username: "user-summary".to_string(),
+                password: "super-secret-password".to_string(),
+                database_name: "db-summary".to_string(),
+                engine: "postgres".to_string(),
+                address_private: "provisioner".to_string(),
+                address_public: "pg.shuttle.rs".to_string(),
+                port: "5432".to_string(),
+                ..Default::default()
+            };
+
+            let summary =
+                ProvisionSummary::new(&request, Some(&response), Duration::from_millis(1500));
+
+            for output in [summary.to_string(), summary.to_json()] {
+                assert!(output.contains("pg.shuttle.rs:5432"), "{output}");
+                assert!(output.contains("summary"), "{output}");
+                assert!(!output.contains("super-secret-password"), "{output}");
+            }
+            assert_eq!(summary.status, ProvisionStatus::Ready);
+            assert_eq!(summary.duration_ms, 1500);
+        }
+
+        #[test]
+        fn failed_summary() {
+            let request = DatabaseRequest {
+                project_name: "failed".to_string(),
+                db_type: Some(database_request::DbType::AwsRds(AwsRds {
+                    engine: Some(aws_rds::Engine::Mysql(RdsConfig::default())),
+                })),
+                ..Default::default()
+            };
+
+            let summary = ProvisionSummary::new(&request, None, Duration::from_secs(2));
+
+            assert_eq!(summary.status, ProvisionStatus::Failed);
+            assert_eq!(summary.engine, "mysql");
+            assert_eq!(summary.kind, "aws_rds");
+            assert_eq!(summary.endpoint, None);
+        }
+    }
 }
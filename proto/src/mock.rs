@@ -0,0 +1,312 @@
+//! A stand-in for the provisioner, for crates which talk to it over gRPC to test against without
+//! a real database or AWS
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tonic::{Code, Request, Response, Status};
+
+use crate::provisioner::{
+    aws_rds, database_request::DbType, provision_result, provisioner_server::Provisioner, AwsRds,
+    DatabaseRequest, DatabaseResponse, DeleteDatabaseResponse, DescribeDatabaseResponse,
+    EngineInfo, ListDatabasesRequest, ListDatabasesResponse, ProvisionDatabasesRequest,
+    ProvisionDatabasesResponse, ProvisionError, ProvisionResult, ReaderCredentials,
+    RoleCredentials, SupportedEnginesRequest, SupportedEnginesResponse,
+};
+
+/// Password handed out for every database
+pub const MOCK_PASSWORD: &str = "password";
+
+/// Answers every request with a made-up database, or with the error it is told to fail with.
+/// Clones share their state, so a test can keep a clone to look at the requests a server got
+#[derive(Clone, Default)]
+pub struct MockProvisioner(Arc<Mutex<MockState>>);
+
+#[derive(Default)]
+struct MockState {
+    error: Option<(Code, String)>,
+    /// Provision requests left to fail with `error`. All of them fail when this is not set
+    errors_left: Option<usize>,
+    delay: Option<Duration>,
+    requests: Vec<DatabaseRequest>,
+}
+
+impl MockProvisioner {
+    /// Fail every provision request with this code and message
+    pub fn with_error(self, code: Code, message: impl Into<String>) -> Self {
+        self.state().error = Some((code, message.into()));
+
+        self
+    }
+
+    /// Fail only the first `times` provision requests with this code and message, like a
+    /// provisioner which is restarting
+    pub fn with_error_times(self, code: Code, message: impl Into<String>, times: usize) -> Self {
+        self.state().errors_left = Some(times);
+
+        self.with_error(code, message)
+    }
+
+    /// Take this long to answer provision requests, like a real provisioner waiting on AWS
+    pub fn with_delay(self, delay: Duration) -> Self {
+        self.state().delay = Some(delay);
+
+        self
+    }
+
+    /// Provision requests received so far
+    pub fn requests(&self) -> Vec<DatabaseRequest> {
+        self.state().requests.clone()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.0.lock().unwrap()
+    }
+
+    async fn provision(&self, request: DatabaseRequest) -> Result<DatabaseResponse, Status> {
+        let delay = self.state().delay;
+        if let Some(delay) = delay {
+            tokio::time::sleep(delay).await;
+        }
+
+        let mut state = self.state();
+        state.requests.push(request.clone());
+
+        if let Some((code, message)) = state.error.clone() {
+            if state.errors_left != Some(0) {
+                state.errors_left = state.errors_left.map(|left| left - 1);
+
+                return Err(Status::new(code, message));
+            }
+        }
+
+        let project_name = &request.project_name;
+        let response = match request.db_type {
+            Some(DbType::Shared(shared)) => DatabaseResponse {
+                engine: "postgres".to_string(),
+                username: format!("user-{project_name}"),
+                reader: shared.reader.then(|| ReaderCredentials {
+                    username: format!("user-{project_name}_reader"),
+                    password: MOCK_PASSWORD.to_string(),
+                }),
+                roles: shared
+                    .roles
+                    .iter()
+                    .map(|role| RoleCredentials {
+                        username: format!("user-{project_name}_{}", role.suffix),
+                        password: MOCK_PASSWORD.to_string(),
+                    })
+                    .collect(),
+                database_name: if shared.database_name.is_empty() {
+                    format!("db-{project_name}")
+                } else {
+                    shared.database_name
+                },
+                address_private: "provisioner".to_string(),
+                address_public: "localhost".to_string(),
+                port: "5432".to_string(),
+                engine_version: "14.6".to_string(),
+                ..Default::default()
+            },
+            Some(DbType::SharedRedis(_)) => DatabaseResponse {
+                engine: "redis".to_string(),
+                username: format!("user-{project_name}"),
+                password: MOCK_PASSWORD.to_string(),
+                database_name: "0".to_string(),
+                address_private: "redis".to_string(),
+                address_public: "localhost".to_string(),
+                port: "6379".to_string(),
+                key_prefix: format!("user-{project_name}:"),
+                ..Default::default()
+            },
+            Some(DbType::AwsRds(AwsRds {
+                engine: Some(engine),
+            })) => {
+                let address = format!("{project_name}-{engine}.rds.test");
+                let (port, engine_version) = match engine {
+                    aws_rds::Engine::Postgres(_) => ("5432", "14.6"),
+                    aws_rds::Engine::Mysql(_) => ("3306", "8.0.32"),
+                    aws_rds::Engine::Mariadb(_) => ("3306", "10.6.12"),
+                };
+
+                DatabaseResponse {
+                    engine: engine.to_string(),
+                    username: "master".to_string(),
+                    database_name: engine.to_string(),
+                    address_private: address.clone(),
+                    address_public: address,
+                    port: port.to_string(),
+                    engine_version: engine_version.to_string(),
+                    is_publicly_accessible: true,
+                    ..Default::default()
+                }
+            }
+            Some(DbType::AwsRds(AwsRds { engine: None })) | None => {
+                return Err(Status::invalid_argument("request has no database type"))
+            }
+        };
+
+        Ok(DatabaseResponse {
+            password: MOCK_PASSWORD.to_string(),
+            correlation_id: request.correlation_id,
+            created: true,
+            ..response
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl Provisioner for MockProvisioner {
+    async fn provision_database(
+        &self,
+        request: Request<DatabaseRequest>,
+    ) -> Result<Response<DatabaseResponse>, Status> {
+        let response = self.provision(request.into_inner()).await?;
+
+        Ok(Response::new(response))
+    }
+
+    async fn provision_databases(
+        &self,
+        request: Request<ProvisionDatabasesRequest>,
+    ) -> Result<Response<ProvisionDatabasesResponse>, Status> {
+        let mut results = Vec::new();
+        for request in request.into_inner().requests {
+            let result = match self.provision(request).await {
+                Ok(database) => provision_result::Result::Database(database),
+                Err(status) => provision_result::Result::Error(ProvisionError {
+                    code: status.code() as i32,
+                    message: status.message().to_string(),
+                }),
+            };
+
+            results.push(ProvisionResult {
+                result: Some(result),
+            });
+        }
+
+        Ok(Response::new(ProvisionDatabasesResponse { results }))
+    }
+
+    async fn delete_database(
+        &self,
+        _request: Request<DatabaseRequest>,
+    ) -> Result<Response<DeleteDatabaseResponse>, Status> {
+        Ok(Response::new(DeleteDatabaseResponse::default()))
+    }
+
+    async fn list_databases(
+        &self,
+        _request: Request<ListDatabasesRequest>,
+    ) -> Result<Response<ListDatabasesResponse>, Status> {
+        Ok(Response::new(ListDatabasesResponse::default()))
+    }
+
+    async fn describe_database(
+        &self,
+        _request: Request<DatabaseRequest>,
+    ) -> Result<Response<DescribeDatabaseResponse>, Status> {
+        Ok(Response::new(DescribeDatabaseResponse::default()))
+    }
+
+    async fn rotate_credentials(
+        &self,
+        request: Request<DatabaseRequest>,
+    ) -> Result<Response<DatabaseResponse>, Status> {
+        let response = self.provision(request.into_inner()).await?;
+
+        Ok(Response::new(DatabaseResponse {
+            created: false,
+            ..response
+        }))
+    }
+
+    async fn get_connection_info(
+        &self,
+        request: Request<DatabaseRequest>,
+    ) -> Result<Response<DatabaseResponse>, Status> {
+        let response = self.provision(request.into_inner()).await?;
+
+        Ok(Response::new(DatabaseResponse {
+            created: false,
+            ..response
+        }))
+    }
+
+    async fn supported_engines(
+        &self,
+        _request: Request<SupportedEnginesRequest>,
+    ) -> Result<Response<SupportedEnginesResponse>, Status> {
+        let engine = |engine: &str, default_port: &str, shared: bool, dedicated: bool| EngineInfo {
+            engine: engine.to_string(),
+            default_port: default_port.to_string(),
+            shared,
+            dedicated,
+        };
+
+        Ok(Response::new(SupportedEnginesResponse {
+            engines: vec![
+                engine("postgres", "5432", true, true),
+                engine("mysql", "3306", false, true),
+                engine("mariadb", "3306", false, true),
+                engine("redis", "6379", true, false),
+            ],
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::provisioner::{RdsConfig, Shared};
+
+    use super::*;
+
+    fn request(db_type: DbType) -> Request<DatabaseRequest> {
+        Request::new(DatabaseRequest {
+            project_name: "mocked".to_string(),
+            db_type: Some(db_type),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn responses() {
+        let mock = MockProvisioner::default();
+
+        let shared = mock
+            .provision_database(request(DbType::Shared(Shared::default())))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(shared.username, "user-mocked");
+        assert_eq!(shared.database_name, "db-mocked");
+        assert_eq!(shared.password, MOCK_PASSWORD);
+
+        let rds = mock
+            .provision_database(request(DbType::AwsRds(AwsRds {
+                engine: Some(aws_rds::Engine::Mysql(RdsConfig::default())),
+            })))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(rds.engine, "mysql");
+        assert_eq!(rds.port, "3306");
+
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn error() {
+        let mock = MockProvisioner::default().with_error(Code::Unavailable, "try again");
+
+        let status = mock
+            .provision_database(request(DbType::Shared(Shared::default())))
+            .await
+            .unwrap_err();
+
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(status.message(), "try again");
+    }
+}
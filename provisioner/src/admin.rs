@@ -0,0 +1,133 @@
+use std::{collections::HashSet, convert::Infallible, net::SocketAddr, sync::Arc};
+
+use hyper::{
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::MyProvisioner;
+
+/// Body of requests to the orphan endpoints
+#[derive(Debug, Deserialize)]
+struct LiveProjects {
+    live_projects: HashSet<String>,
+}
+
+/// Serves the operator endpoints for reconciling what the provisioner made with the projects
+/// which still exist, on its own port next to the gRPC server. Every request needs `token` as
+/// its bearer token.
+///
+/// - `POST /orphans` lists what belongs to none of the `live_projects` in the JSON body
+/// - `POST /orphans/cleanup?confirm=true` deletes those orphans, and refuses to without
+///   `confirm=true`
+pub async fn serve_admin(
+    provisioner: Arc<MyProvisioner>,
+    addr: SocketAddr,
+    token: String,
+) -> Result<(), hyper::Error> {
+    let token = Arc::new(token);
+    let make_service = make_service_fn(move |_| {
+        let provisioner = provisioner.clone();
+        let token = token.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let provisioner = provisioner.clone();
+                let token = token.clone();
+
+                async move { Ok::<_, Infallible>(handle(&provisioner, &token, request).await) }
+            }))
+        }
+    });
+
+    Server::bind(&addr).serve(make_service).await
+}
+
+async fn handle(
+    provisioner: &MyProvisioner,
+    token: &str,
+    request: Request<Body>,
+) -> Response<Body> {
+    let expected = format!("Bearer {token}");
+    let authorized = request
+        .headers()
+        .get(AUTHORIZATION)
+        .map_or(false, |header| {
+            constant_time_eq(header.as_bytes(), expected.as_bytes())
+        });
+    if !authorized {
+        return text(StatusCode::UNAUTHORIZED, "missing or wrong bearer token");
+    }
+
+    if request.method() != Method::POST {
+        return text(StatusCode::METHOD_NOT_ALLOWED, "only POST is supported");
+    }
+
+    let cleanup = match request.uri().path() {
+        "/orphans" => false,
+        "/orphans/cleanup" => true,
+        _ => return text(StatusCode::NOT_FOUND, "no such endpoint"),
+    };
+
+    // Deleting is irreversible, so it has to be asked for in so many words
+    let confirmed = request.uri().query().map_or(false, |query| {
+        query.split('&').any(|pair| pair == "confirm=true")
+    });
+    if cleanup && !confirmed {
+        return text(
+            StatusCode::BAD_REQUEST,
+            "cleaning up deletes databases, add `confirm=true` to go ahead",
+        );
+    }
+
+    let body = match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => body,
+        Err(error) => return text(StatusCode::BAD_REQUEST, &error.to_string()),
+    };
+    let live_projects = match serde_json::from_slice::<LiveProjects>(&body) {
+        Ok(body) => body.live_projects,
+        Err(error) => return text(StatusCode::BAD_REQUEST, &error.to_string()),
+    };
+
+    let orphans = match provisioner.orphans(&live_projects).await {
+        Ok(orphans) => orphans,
+        Err(error) => {
+            error!(
+                error = &error as &dyn std::error::Error,
+                "failed to find orphans"
+            );
+            return text(StatusCode::INTERNAL_SERVER_ERROR, &error.to_string());
+        }
+    };
+
+    if cleanup {
+        info!(?orphans, "cleaning up orphans");
+        json(&provisioner.cleanup_orphans(&orphans).await)
+    } else {
+        json(&orphans)
+    }
+}
+
+/// Compare without giving away through timing how much of a token was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn text(status: StatusCode, message: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(message.to_string()))
+        .expect("response to be valid")
+}
+
+fn json(value: &impl Serialize) -> Response<Body> {
+    Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            serde_json::to_vec(value).expect("response to serialize"),
+        ))
+        .expect("response to be valid")
+}
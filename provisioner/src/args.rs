@@ -3,12 +3,32 @@ use std::{
     str::FromStr,
 };
 
-use clap::Parser;
+use clap::{ArgEnum, Parser};
 use fqdn::FQDN;
 
+use crate::{
+    config::Network,
+    config::{
+        DEFAULT_AWS_ATTEMPT_TIMEOUT_SECS, DEFAULT_AWS_CALL_TIMEOUT_SECS,
+        DEFAULT_CONNECT_TIMEOUT_SECS, DEFAULT_DB_PREFIX, DEFAULT_IDLE_TIMEOUT_SECS,
+        DEFAULT_LOCK_TIMEOUT_SECS, DEFAULT_MAX_CONNECTIONS, DEFAULT_MAX_LIFETIME_SECS,
+        DEFAULT_MIN_CONNECTIONS, DEFAULT_PROVISION_PARALLELISM, DEFAULT_RDS_ALLOCATED_STORAGE,
+        DEFAULT_RDS_CA_CERTIFICATE, DEFAULT_RDS_INSTANCE_CLASS,
+        DEFAULT_RDS_POLL_INITIAL_INTERVAL_MS, DEFAULT_RDS_POLL_MAX_INTERVAL_MS,
+        DEFAULT_RDS_SUBNET_GROUP, DEFAULT_RDS_WAIT_TIMEOUT_SECS, DEFAULT_REDIS_INTERNAL_ADDRESS,
+        DEFAULT_ROLE_PREFIX, DEFAULT_SSLMODE, DEFAULT_STATEMENT_TIMEOUT_SECS, ENGINES,
+    },
+    instance_class::InstanceClass,
+    server::{DEFAULT_MAX_CONCURRENT_STREAMS, DEFAULT_MAX_MESSAGE_SIZE},
+};
+
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 pub struct Args {
+    /// Format to write logs in. JSON logs are easier to ingest in a log aggregator
+    #[clap(long, arg_enum, env = "PROVISIONER_LOG_FORMAT", default_value = "text")]
+    pub log_format: LogFormat,
+
     /// Address to bind provisioner on
     #[clap(long, env = "PROVISIONER_IP", default_value_t = Ipv4Addr::LOCALHOST.into())]
     pub ip: IpAddr,
@@ -17,24 +37,316 @@ pub struct Args {
     #[clap(long, env = "PROVISIONER_PORT", default_value_t = 5001)]
     pub port: u16,
 
+    /// Most requests a single gRPC connection can have in flight at once
+    #[clap(long, env = "PROVISIONER_MAX_CONCURRENT_STREAMS", default_value_t = DEFAULT_MAX_CONCURRENT_STREAMS)]
+    pub max_concurrent_streams: u32,
+
+    /// Largest request in bytes. Bigger requests are rejected with `ResourceExhausted`
+    #[clap(long, env = "PROVISIONER_MAX_MESSAGE_SIZE", default_value_t = DEFAULT_MAX_MESSAGE_SIZE)]
+    pub max_message_size: usize,
+
+    /// Seconds a request can take before it is cancelled. Requests for new RDS instances can take
+    /// most of an hour, so there is no timeout when not set
+    #[clap(long, env = "PROVISIONER_REQUEST_TIMEOUT")]
+    pub request_timeout: Option<u64>,
+
+    /// Serve gRPC reflection for tools like grpcurl. Meant for debugging, so it is off by default
+    #[clap(long, env = "PROVISIONER_REFLECTION")]
+    pub reflection: bool,
+
+    /// Port to serve the operator endpoints for finding and cleaning up orphaned databases on.
+    /// They are not served when not set
+    #[clap(long, env = "PROVISIONER_ADMIN_PORT", requires = "admin-token")]
+    pub admin_port: Option<u16>,
+
+    /// Bearer token requests to the operator endpoints have to carry
+    #[clap(long, env = "PROVISIONER_ADMIN_TOKEN", hide_env_values = true)]
+    pub admin_token: Option<String>,
+
+    /// Seconds to wait at startup for the shared Postgres to be reachable before taking requests.
+    /// The provisioner starts right away and connects on the first request when not set
+    #[clap(long, env = "PROVISIONER_PG_READY_TIMEOUT")]
+    pub shared_pg_ready_timeout: Option<u64>,
+
+    /// Most requests of a batch to provision at the same time
+    #[clap(long, env = "PROVISIONER_PARALLELISM", default_value_t = DEFAULT_PROVISION_PARALLELISM)]
+    pub provision_parallelism: usize,
+
     /// URI to connect to Postgres for managing shared DB resources
     #[clap(short, long, env = "PROVISIONER_PG_URI", hide_env_values = true)]
     pub shared_pg_uri: String,
 
-    /// Fully qualified domain name this provisioner instance is reachable at
-    #[clap(long, env = "PROVISIONER_FQDN", parse(try_from_str = parse_fqdn))]
+    /// Connections to keep open to the shared Postgres
+    #[clap(long, env = "PROVISIONER_PG_MIN_CONNECTIONS", default_value_t = DEFAULT_MIN_CONNECTIONS)]
+    pub shared_pg_min_connections: u32,
+
+    /// Most connections to open to the shared Postgres
+    #[clap(long, env = "PROVISIONER_PG_MAX_CONNECTIONS", default_value_t = DEFAULT_MAX_CONNECTIONS)]
+    pub shared_pg_max_connections: u32,
+
+    /// Seconds to wait for a connection to the shared Postgres
+    #[clap(long, env = "PROVISIONER_PG_CONNECT_TIMEOUT", default_value_t = DEFAULT_CONNECT_TIMEOUT_SECS)]
+    pub shared_pg_connect_timeout: u64,
+
+    /// Seconds a connection to the shared Postgres can be idle before it is closed
+    #[clap(long, env = "PROVISIONER_PG_IDLE_TIMEOUT", default_value_t = DEFAULT_IDLE_TIMEOUT_SECS)]
+    pub shared_pg_idle_timeout: u64,
+
+    /// Seconds a connection to the shared Postgres is used before it is replaced
+    #[clap(long, env = "PROVISIONER_PG_MAX_LIFETIME", default_value_t = DEFAULT_MAX_LIFETIME_SECS)]
+    pub shared_pg_max_lifetime: u64,
+
+    /// Seconds a statement on the shared Postgres can run before it is cancelled. 0 turns the
+    /// timeout off
+    #[clap(long, env = "PROVISIONER_PG_STATEMENT_TIMEOUT", default_value_t = DEFAULT_STATEMENT_TIMEOUT_SECS)]
+    pub shared_pg_statement_timeout: u64,
+
+    /// Seconds a statement on the shared Postgres can wait for a lock before it is cancelled. 0
+    /// turns the timeout off
+    #[clap(long, env = "PROVISIONER_PG_LOCK_TIMEOUT", default_value_t = DEFAULT_LOCK_TIMEOUT_SECS)]
+    pub shared_pg_lock_timeout: u64,
+
+    /// Create shared databases owned by the provisioner's role and grant the project's role
+    /// privileges on them, instead of making the project's role the owner
+    #[clap(long, env = "PROVISIONER_PG_LEAST_PRIVILEGE")]
+    pub shared_pg_least_privilege: bool,
+
+    /// Prefix of the names of project roles on the shared Postgres. Environments sharing a
+    /// server need different prefixes
+    #[clap(long, env = "PROVISIONER_ROLE_PREFIX", default_value = DEFAULT_ROLE_PREFIX)]
+    pub role_prefix: String,
+
+    /// Prefix of the names of project databases on the shared Postgres
+    #[clap(long, env = "PROVISIONER_DB_PREFIX", default_value = DEFAULT_DB_PREFIX)]
+    pub db_prefix: String,
+
+    /// sslmode clients should use to reach the shared Postgres on its public address
+    #[clap(long, env = "PROVISIONER_PG_SSLMODE", default_value = DEFAULT_SSLMODE)]
+    pub shared_pg_sslmode: String,
+
+    /// Most connections a single project can have open to the shared Postgres. Unlimited when
+    /// not set
+    #[clap(long, env = "PROVISIONER_PG_CONNECTION_LIMIT")]
+    pub shared_pg_connection_limit: Option<u32>,
+
+    /// Keep the passwords of shared Postgres roles in Secrets Manager, so clients can fetch
+    /// their connection info again without a rotation
+    #[clap(long, env = "PROVISIONER_PG_PASSWORD_SECRETS")]
+    pub shared_pg_password_secrets: bool,
+
+    /// Most shared databases a single project can have. Unlimited when not set
+    #[clap(long, env = "PROVISIONER_PG_QUOTA")]
+    pub shared_pg_quota: Option<usize>,
+
+    /// URI to connect to Redis for managing shared caches. Shared caches are disabled when not set
+    #[clap(long, env = "PROVISIONER_REDIS_URI", hide_env_values = true)]
+    pub shared_redis_uri: Option<String>,
+
+    /// Address the shared Redis can be reached at on the internal network
+    #[clap(
+        long,
+        env = "PROVISIONER_REDIS_ADDRESS",
+        default_value = DEFAULT_REDIS_INTERNAL_ADDRESS
+    )]
+    pub shared_redis_internal_address: String,
+
+    /// Address the shared Redis can be reached at from outside the internal network. Shared
+    /// caches are only handed out with their internal address when this is not set
+    #[clap(long, env = "PROVISIONER_REDIS_PUBLIC_ADDRESS")]
+    pub shared_redis_public_address: Option<String>,
+
+    /// Send AWS requests to this endpoint instead of the real AWS, e.g. for LocalStack
+    #[clap(long, env = "PROVISIONER_AWS_ENDPOINT_URL")]
+    pub aws_endpoint_url: Option<String>,
+
+    /// POST a JSON summary of every finished provision to this URL, without any credentials.
+    /// Delivery is best-effort and does not hold up the response
+    #[clap(long, env = "PROVISIONER_WEBHOOK_URL")]
+    pub webhook_url: Option<String>,
+
+    /// Fully qualified domain name this provisioner instance is reachable at.
+    /// This is the public host handed out for shared databases
+    #[clap(
+        long,
+        alias = "shared-pg-public-host",
+        env = "PROVISIONER_FQDN",
+        parse(try_from_str = parse_fqdn)
+    )]
     pub fqdn: FQDN,
 
-    /// Address this provisioner can be reached at on the internal network
+    /// Address this provisioner can be reached at on the internal network.
+    /// This is the private host handed out for shared databases
     #[clap(
         short,
         long,
+        alias = "shared-pg-private-host",
         env = "PROVISIONER_ADDRESS",
         default_value = "provisioner"
     )]
     pub internal_address: String,
+
+    /// Instance class for RDS requests which don't ask for one
+    #[clap(
+        long,
+        env = "PROVISIONER_RDS_DEFAULT_INSTANCE_CLASS",
+        default_value = DEFAULT_RDS_INSTANCE_CLASS
+    )]
+    pub rds_default_instance_class: InstanceClass,
+
+    /// Storage in GiB new RDS instances start with
+    #[clap(long, env = "PROVISIONER_RDS_ALLOCATED_STORAGE", default_value_t = DEFAULT_RDS_ALLOCATED_STORAGE)]
+    pub rds_allocated_storage: i32,
+
+    /// Smallest RDS instance class to allow for an engine, given as `engine=class`
+    #[clap(
+        long,
+        env = "PROVISIONER_RDS_MIN_INSTANCE_CLASS",
+        value_delimiter = ',',
+        parse(try_from_str = parse_min_instance_class)
+    )]
+    pub rds_min_instance_class: Vec<(String, InstanceClass)>,
+
+    /// Create single-AZ instances when the subnet group spans too few availability zones
+    /// for a multi-AZ request, instead of rejecting the request
+    #[clap(long, env = "PROVISIONER_RDS_SINGLE_AZ_FALLBACK")]
+    pub rds_single_az_fallback: bool,
+
+    /// Subnet group to create RDS instances in. Pass an empty value to use the default VPC
+    /// subnet group
+    #[clap(
+        long,
+        env = "PROVISIONER_RDS_SUBNET_GROUP",
+        default_value = DEFAULT_RDS_SUBNET_GROUP
+    )]
+    pub rds_subnet_group: String,
+
+    /// CA new RDS instances get their server certificate from. Pass an empty value to use the
+    /// AWS default
+    #[clap(
+        long,
+        env = "PROVISIONER_RDS_CA_CERTIFICATE",
+        default_value = DEFAULT_RDS_CA_CERTIFICATE
+    )]
+    pub rds_ca_certificate: String,
+
+    /// Address to hand out as the public address of RDS instances instead of their AWS endpoint,
+    /// like `{instance}.db.example.com` behind a proxy. `{instance}` is replaced with the
+    /// identifier of the instance
+    #[clap(long, env = "PROVISIONER_RDS_PUBLIC_HOST")]
+    pub rds_public_host: Option<String>,
+
+    /// VPC security groups to put RDS instances in when a request does not name any
+    #[clap(
+        long,
+        env = "PROVISIONER_RDS_SECURITY_GROUP_IDS",
+        value_delimiter = ','
+    )]
+    pub rds_security_group_ids: Vec<String>,
+
+    /// Network RDS requests for an environment go into, given as
+    /// `environment=subnet_group:security_group:...`. Leave out the subnet group to use the
+    /// default VPC subnet group
+    #[clap(
+        long,
+        env = "PROVISIONER_RDS_ENVIRONMENTS",
+        value_delimiter = ',',
+        parse(try_from_str = parse_environment)
+    )]
+    pub rds_environment: Vec<(String, Network)>,
+
+    /// Percentage to grow the storage of full RDS instances by when they are requested. Requests
+    /// for full instances are rejected when not set
+    #[clap(long, env = "PROVISIONER_RDS_STORAGE_FULL_INCREASE")]
+    pub rds_storage_full_increase: Option<u32>,
+
+    /// Most RDS instances a single project can have. Unlimited when not set
+    #[clap(long, env = "PROVISIONER_RDS_QUOTA")]
+    pub rds_quota: Option<usize>,
+
+    /// Turn off deletion protection on RDS instances which are asked to be deleted, instead
+    /// of refusing to delete them
+    #[clap(long, env = "PROVISIONER_RDS_DISABLE_DELETION_PROTECTION")]
+    pub rds_disable_deletion_protection: bool,
+
+    /// Store the master passwords of RDS instances in Secrets Manager and respond with the ARN
+    /// of their secret, so passwords never go over the wire
+    #[clap(long, env = "PROVISIONER_RDS_PASSWORD_SECRETS")]
+    pub rds_password_secrets: bool,
+
+    /// Milliseconds to wait before polling the status of an RDS instance again. The wait doubles
+    /// after every poll. Lower it for LocalStack, raise it when the AWS API rate limit is tight
+    #[clap(long, env = "PROVISIONER_RDS_POLL_INITIAL_INTERVAL", default_value_t = DEFAULT_RDS_POLL_INITIAL_INTERVAL_MS)]
+    pub rds_poll_initial_interval: u64,
+
+    /// Most milliseconds to wait between polls of the status of an RDS instance
+    #[clap(long, env = "PROVISIONER_RDS_POLL_MAX_INTERVAL", default_value_t = DEFAULT_RDS_POLL_MAX_INTERVAL_MS)]
+    pub rds_poll_max_interval: u64,
+
+    /// Seconds to wait for an RDS instance to reach a status before giving up on the request
+    #[clap(long, env = "PROVISIONER_RDS_WAIT_TIMEOUT", default_value_t = DEFAULT_RDS_WAIT_TIMEOUT_SECS)]
+    pub rds_wait_timeout: u64,
+
+    /// Seconds an AWS API call can take, retries included, before it fails
+    #[clap(long, env = "PROVISIONER_AWS_CALL_TIMEOUT", default_value_t = DEFAULT_AWS_CALL_TIMEOUT_SECS)]
+    pub aws_call_timeout: u64,
+
+    /// Seconds a single attempt of an AWS API call can take before it is retried. Cannot be more
+    /// than the call timeout
+    #[clap(long, env = "PROVISIONER_AWS_ATTEMPT_TIMEOUT", default_value_t = DEFAULT_AWS_ATTEMPT_TIMEOUT_SECS)]
+    pub aws_attempt_timeout: u64,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line, with the fields of the current spans
+    Json,
 }
 
 fn parse_fqdn(src: &str) -> Result<FQDN, String> {
     FQDN::from_str(src).map_err(|e| format!("{e:?}"))
 }
+
+fn parse_min_instance_class(src: &str) -> Result<(String, InstanceClass), String> {
+    let (engine, class) = src
+        .split_once('=')
+        .ok_or_else(|| format!("expected `engine=class`, got '{src}'"))?;
+
+    if !ENGINES.contains(&engine) {
+        return Err(format!(
+            "unknown engine '{engine}', expected one of {}",
+            ENGINES.join(", ")
+        ));
+    }
+
+    Ok((engine.to_string(), class.parse()?))
+}
+
+fn parse_environment(src: &str) -> Result<(String, Network), String> {
+    let (environment, network) = src.split_once('=').ok_or_else(|| {
+        format!("expected `environment=subnet_group:security_group`, got '{src}'")
+    })?;
+
+    if environment.is_empty() {
+        return Err(format!("environment of '{src}' is empty"));
+    }
+
+    let mut parts = network.split(':');
+    let subnet_group = parts
+        .next()
+        .filter(|group| !group.is_empty())
+        .map(str::to_string);
+    let security_group_ids = parts
+        .filter(|group| !group.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    Ok((
+        environment.to_string(),
+        Network {
+            subnet_group,
+            security_group_ids,
+        },
+    ))
+}
@@ -0,0 +1,122 @@
+use std::{future::Future, iter, time::Duration};
+
+use tokio::time::sleep;
+use tracing::warn;
+
+/// Exponentially growing delays between attempts, capped at `max`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Backoff {
+    /// Endless delays, doubling from `initial` up to `max`
+    pub fn delays(&self) -> impl Iterator<Item = Duration> {
+        let max = self.max;
+
+        iter::successors(Some(self.initial.min(max)), move |delay| {
+            Some((*delay * 2).min(max))
+        })
+    }
+
+    /// Run `call` up to `attempts` times, backing off between attempts while its error is
+    /// retryable
+    pub async fn retry<T, E, F, Fut>(
+        &self,
+        attempts: usize,
+        is_retryable: impl Fn(&E) -> bool,
+        mut call: F,
+    ) -> Result<T, E>
+    where
+        E: std::error::Error,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut delays = self.delays().take(attempts.saturating_sub(1));
+
+        loop {
+            match call().await {
+                Err(error) if is_retryable(&error) => match delays.next() {
+                    Some(delay) => {
+                        warn!(error = &error as &dyn std::error::Error, ?delay, "retrying");
+                        sleep(delay).await;
+                    }
+                    None => return Err(error),
+                },
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    const BACKOFF: Backoff = Backoff {
+        initial: Duration::from_millis(1),
+        max: Duration::from_millis(5),
+    };
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("failed")]
+    struct Failed {
+        retryable: bool,
+    }
+
+    #[test]
+    fn delays() {
+        let delays: Vec<_> = BACKOFF.delays().take(5).collect();
+
+        assert_eq!(delays, [1, 2, 4, 5, 5].map(Duration::from_millis).to_vec());
+    }
+
+    #[tokio::test]
+    async fn retry() {
+        let calls = AtomicUsize::new(0);
+        let result = BACKOFF
+            .retry(
+                3,
+                |error: &Failed| error.retryable,
+                || async {
+                    match calls.fetch_add(1, Ordering::SeqCst) {
+                        0 => Err(Failed { retryable: true }),
+                        call => Ok(call),
+                    }
+                },
+            )
+            .await;
+        assert_eq!(result.unwrap(), 1);
+
+        let calls = AtomicUsize::new(0);
+        let result = BACKOFF
+            .retry(
+                3,
+                |error: &Failed| error.retryable,
+                || async {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Err::<(), _>(Failed { retryable: false })
+                },
+            )
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let calls = AtomicUsize::new(0);
+        let result = BACKOFF
+            .retry(
+                3,
+                |error: &Failed| error.retryable,
+                || async {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Err::<(), _>(Failed { retryable: true })
+                },
+            )
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,552 @@
+use std::{collections::HashMap, time::Duration};
+
+use aws_config::timeout;
+use aws_smithy_types::tristate::TriState;
+
+use crate::{instance_class::InstanceClass, Error};
+
+/// Engines a minimum instance class can be configured for
+pub const ENGINES: [&str; 3] = ["postgres", "mysql", "mariadb"];
+
+pub const DEFAULT_MIN_CONNECTIONS: u32 = 4;
+pub const DEFAULT_MAX_CONNECTIONS: u32 = 12;
+pub const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 60;
+pub const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 5 * 60;
+pub const DEFAULT_MAX_LIFETIME_SECS: u64 = 30 * 60;
+pub const DEFAULT_STATEMENT_TIMEOUT_SECS: u64 = 60;
+pub const DEFAULT_LOCK_TIMEOUT_SECS: u64 = 10;
+pub const DEFAULT_PROVISION_PARALLELISM: usize = 4;
+pub const DEFAULT_REDIS_INTERNAL_ADDRESS: &str = "redis";
+pub const DEFAULT_RDS_SUBNET_GROUP: &str = "shuttle_rds";
+pub const DEFAULT_RDS_CA_CERTIFICATE: &str = "rds-ca-rsa2048-g1";
+pub const DEFAULT_SSLMODE: &str = "require";
+pub const DEFAULT_ROLE_PREFIX: &str = "user-";
+pub const DEFAULT_DB_PREFIX: &str = "db-";
+pub const DEFAULT_RDS_INSTANCE_CLASS: &str = "db.t4g.micro";
+pub const DEFAULT_RDS_ALLOCATED_STORAGE: i32 = 20;
+pub const DEFAULT_RDS_POLL_INITIAL_INTERVAL_MS: u64 = 1000;
+pub const DEFAULT_RDS_POLL_MAX_INTERVAL_MS: u64 = 10 * 1000;
+pub const DEFAULT_RDS_WAIT_TIMEOUT_SECS: u64 = 60 * 60;
+pub const DEFAULT_AWS_CALL_TIMEOUT_SECS: u64 = 5 * 60;
+pub const DEFAULT_AWS_ATTEMPT_TIMEOUT_SECS: u64 = 2 * 60;
+
+/// Part of the RDS public host which is replaced with the identifier of an instance
+const INSTANCE_PLACEHOLDER: &str = "{instance}";
+
+/// Values libpq accepts for `sslmode`
+const SSLMODES: [&str; 6] = [
+    "disable",
+    "allow",
+    "prefer",
+    "require",
+    "verify-ca",
+    "verify-full",
+];
+
+/// Tunable settings for [MyProvisioner](crate::MyProvisioner)
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Instance class for RDS requests which don't ask for one
+    pub default_instance_class: InstanceClass,
+
+    /// Storage in GiB new RDS instances start with
+    pub allocated_storage: i32,
+
+    /// Smallest instance class allowed for each engine
+    pub min_instance_classes: HashMap<String, InstanceClass>,
+
+    /// Connections to keep open to the shared Postgres
+    pub min_connections: u32,
+
+    /// Most connections to open to the shared Postgres
+    pub max_connections: u32,
+
+    /// How long to wait for a connection to the shared Postgres
+    pub connect_timeout: Duration,
+
+    /// How long a connection to the shared Postgres can sit unused before it is closed. This
+    /// should be below the idle timeout of any load balancer or NAT in front of the server
+    pub idle_timeout: Duration,
+
+    /// How long a connection to the shared Postgres is used before it is replaced
+    pub max_lifetime: Duration,
+
+    /// How long a statement on the shared Postgres can run before it is cancelled, so a stuck
+    /// statement frees its connection. Zero turns the timeout off
+    pub statement_timeout: Duration,
+
+    /// How long a statement on the shared Postgres can wait for a lock before it is cancelled.
+    /// Zero turns the timeout off
+    pub lock_timeout: Duration,
+
+    /// Create shared databases owned by the provisioner's own role, and only grant the project's
+    /// role privileges on them. The project's role then cannot drop its database or change its
+    /// settings. Databases are owned by the project's role otherwise
+    pub least_privilege: bool,
+
+    /// sslmode clients should use to reach the shared Postgres on its public address
+    pub sslmode: String,
+
+    /// Prefix of the names of project roles on the shared Postgres. Environments sharing a server
+    /// need different prefixes to keep their roles apart
+    pub role_prefix: String,
+
+    /// Prefix of the names of project databases on the shared Postgres
+    pub db_prefix: String,
+
+    /// Most connections the role of a project can have open to the shared Postgres at once, so
+    /// one project cannot starve the others. There is no limit when this is not set
+    pub connection_limit: Option<u32>,
+
+    /// Also keep the passwords of shared Postgres roles in Secrets Manager, so their connection
+    /// info can be fetched again without rotating them. Responses still carry the password
+    pub shared_password_secrets: bool,
+
+    /// Most requests of a batch to provision at the same time
+    pub provision_parallelism: usize,
+
+    /// Fall back to a single-AZ instance when the subnet group cannot support multi-AZ,
+    /// instead of rejecting the request
+    pub single_az_fallback: bool,
+
+    /// Percentage to grow the storage of an RDS instance by when it is found to be full. AWS only
+    /// allows increases of at least 10%. Requests for full instances are rejected when this is
+    /// not set
+    pub storage_full_increase: Option<u32>,
+
+    /// Subnet group to create RDS instances in. Instances go into the default VPC subnet group
+    /// when this is not set
+    pub subnet_group: Option<String>,
+
+    /// CA new RDS instances get their server certificate from. AWS picks its default CA when
+    /// this is not set, which can be one that is about to expire
+    pub ca_certificate: Option<String>,
+
+    /// Address to hand out as the public address of RDS instances instead of the endpoint AWS
+    /// gives them, for setups where users connect through a proxy or a CNAME. `{instance}` is
+    /// replaced with the identifier of the instance, like in `{instance}.db.example.com`. The
+    /// endpoint is handed out as is when this is not set
+    pub rds_public_host: Option<String>,
+
+    /// VPC security groups for RDS instances which don't ask for their own
+    pub security_group_ids: Vec<String>,
+
+    /// Networks RDS requests can pick by naming their environment. Requests which don't name
+    /// one go into `subnet_group` and `security_group_ids`
+    pub environments: HashMap<String, Network>,
+
+    /// Turn off deletion protection when asked to delete a protected instance, instead of
+    /// refusing the delete
+    pub disable_deletion_protection: bool,
+
+    /// Store the master passwords of RDS instances in Secrets Manager, and hand out the ARN of
+    /// their secret instead of the password itself
+    pub password_secrets: bool,
+
+    /// Delay before polling an RDS instance's status again. It doubles after every poll
+    pub rds_poll_initial_interval: Duration,
+
+    /// Longest delay between polls of an RDS instance's status
+    pub rds_poll_max_interval: Duration,
+
+    /// Longest to wait for an RDS instance to reach a status. Creating a multi-AZ instance from a
+    /// large snapshot is the slowest operation, and it takes well under the default
+    pub rds_wait_timeout: Duration,
+
+    /// Longest an AWS API call can take, retries included. This is about the call itself, like
+    /// `CreateDBInstance` being accepted, not the instance becoming available
+    pub aws_call_timeout: Duration,
+
+    /// Longest a single attempt of an AWS API call can take before it is retried
+    pub aws_attempt_timeout: Duration,
+
+    /// Most shared databases a project can have. There is no limit when this is not set
+    pub shared_db_quota: Option<usize>,
+
+    /// Most RDS instances a project can have. There is no limit when this is not set
+    pub rds_quota: Option<usize>,
+
+    /// Endpoint to send AWS requests to instead of the real AWS, like a LocalStack instance
+    pub aws_endpoint_url: Option<String>,
+
+    /// URL to POST a summary of every finished provision to. No webhook is sent when this is
+    /// not set
+    pub webhook_url: Option<String>,
+
+    /// URI to connect to the shared Redis with. Shared Redis is disabled when this is not set
+    pub shared_redis_uri: Option<String>,
+
+    /// Address the shared Redis can be reached at on the internal network
+    pub shared_redis_internal_address: String,
+
+    /// Address the shared Redis can be reached at from outside the internal network. Responses
+    /// leave `address_public` empty when this is not set
+    pub shared_redis_public_address: Option<String>,
+}
+
+/// Where the RDS instances of an environment go, like the VPC of staging or of production
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Network {
+    /// Subnet group to create instances in. The default VPC subnet group when not set
+    pub subnet_group: Option<String>,
+
+    /// Security groups for instances which don't ask for their own
+    pub security_group_ids: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            default_instance_class: DEFAULT_RDS_INSTANCE_CLASS
+                .parse()
+                .expect("default instance class to be valid"),
+            allocated_storage: DEFAULT_RDS_ALLOCATED_STORAGE,
+            min_instance_classes: Default::default(),
+            min_connections: DEFAULT_MIN_CONNECTIONS,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            connect_timeout: Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECS),
+            idle_timeout: Duration::from_secs(DEFAULT_IDLE_TIMEOUT_SECS),
+            max_lifetime: Duration::from_secs(DEFAULT_MAX_LIFETIME_SECS),
+            statement_timeout: Duration::from_secs(DEFAULT_STATEMENT_TIMEOUT_SECS),
+            lock_timeout: Duration::from_secs(DEFAULT_LOCK_TIMEOUT_SECS),
+            least_privilege: false,
+            sslmode: DEFAULT_SSLMODE.to_string(),
+            role_prefix: DEFAULT_ROLE_PREFIX.to_string(),
+            db_prefix: DEFAULT_DB_PREFIX.to_string(),
+            connection_limit: None,
+            shared_password_secrets: false,
+            provision_parallelism: DEFAULT_PROVISION_PARALLELISM,
+            single_az_fallback: false,
+            storage_full_increase: None,
+            subnet_group: Some(DEFAULT_RDS_SUBNET_GROUP.to_string()),
+            ca_certificate: Some(DEFAULT_RDS_CA_CERTIFICATE.to_string()),
+            rds_public_host: None,
+            security_group_ids: Vec::new(),
+            environments: Default::default(),
+            disable_deletion_protection: false,
+            password_secrets: false,
+            rds_poll_initial_interval: Duration::from_millis(DEFAULT_RDS_POLL_INITIAL_INTERVAL_MS),
+            rds_poll_max_interval: Duration::from_millis(DEFAULT_RDS_POLL_MAX_INTERVAL_MS),
+            rds_wait_timeout: Duration::from_secs(DEFAULT_RDS_WAIT_TIMEOUT_SECS),
+            aws_call_timeout: Duration::from_secs(DEFAULT_AWS_CALL_TIMEOUT_SECS),
+            aws_attempt_timeout: Duration::from_secs(DEFAULT_AWS_ATTEMPT_TIMEOUT_SECS),
+            shared_db_quota: None,
+            rds_quota: None,
+            aws_endpoint_url: None,
+            webhook_url: None,
+            shared_redis_uri: None,
+            shared_redis_internal_address: DEFAULT_REDIS_INTERNAL_ADDRESS.to_string(),
+            shared_redis_public_address: None,
+        }
+    }
+}
+
+impl Config {
+    /// Check that the settings make sense together
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.min_connections > self.max_connections {
+            return Err(Error::InvalidConfig(format!(
+                "min connections ({}) cannot be more than max connections ({})",
+                self.min_connections, self.max_connections
+            )));
+        }
+
+        if self.allocated_storage <= 0 {
+            return Err(Error::InvalidConfig(format!(
+                "allocated storage has to be positive, got {} GiB",
+                self.allocated_storage
+            )));
+        }
+
+        if self.provision_parallelism == 0 {
+            return Err(Error::InvalidConfig(
+                "provision parallelism has to be at least 1".to_string(),
+            ));
+        }
+
+        if self.rds_poll_initial_interval.is_zero() {
+            return Err(Error::InvalidConfig(
+                "RDS poll interval cannot be zero".to_string(),
+            ));
+        }
+
+        if self.rds_poll_initial_interval > self.rds_poll_max_interval {
+            return Err(Error::InvalidConfig(format!(
+                "initial RDS poll interval ({:?}) cannot be more than the max interval ({:?})",
+                self.rds_poll_initial_interval, self.rds_poll_max_interval
+            )));
+        }
+
+        if self.aws_attempt_timeout > self.aws_call_timeout {
+            return Err(Error::InvalidConfig(format!(
+                "AWS attempt timeout ({:?}) cannot be more than the call timeout ({:?})",
+                self.aws_attempt_timeout, self.aws_call_timeout
+            )));
+        }
+
+        if let Some(increase) = self.storage_full_increase {
+            if increase < 10 {
+                return Err(Error::InvalidConfig(format!(
+                    "storage increase has to be at least 10%, got {increase}%"
+                )));
+            }
+        }
+
+        // Prefixes end up in SQL identifiers, so hold them to the same rules as database names
+        for (kind, prefix) in [("role", &self.role_prefix), ("database", &self.db_prefix)] {
+            let is_valid = prefix.starts_with(|c: char| c.is_ascii_lowercase())
+                && prefix.bytes().all(|byte| {
+                    byte.is_ascii_lowercase()
+                        || byte.is_ascii_digit()
+                        || byte == b'-'
+                        || byte == b'_'
+                });
+
+            if !is_valid {
+                return Err(Error::InvalidConfig(format!(
+                    "invalid {kind} prefix '{prefix}', it has to start with a lowercase letter and can only contain lowercase letters, numbers, dashes and underscores"
+                )));
+            }
+        }
+
+        if let Some(template) = &self.rds_public_host {
+            let host = template.replace(INSTANCE_PLACEHOLDER, "instance");
+            let is_valid = !host.is_empty()
+                && host
+                    .bytes()
+                    .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'.');
+
+            if !is_valid {
+                return Err(Error::InvalidConfig(format!(
+                    "invalid RDS public host '{template}', it can only contain letters, numbers, dashes, dots and {INSTANCE_PLACEHOLDER}"
+                )));
+            }
+        }
+
+        if !SSLMODES.contains(&self.sslmode.as_str()) {
+            return Err(Error::InvalidConfig(format!(
+                "unknown sslmode '{}', expected one of {}",
+                self.sslmode,
+                SSLMODES.join(", ")
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Timeouts of the calls made by the AWS clients
+    pub fn aws_timeouts(&self) -> timeout::Config {
+        let api = timeout::Api::new()
+            .with_call_timeout(TriState::Set(self.aws_call_timeout))
+            .with_call_attempt_timeout(TriState::Set(self.aws_attempt_timeout));
+
+        timeout::Config::new().with_api_timeouts(api)
+    }
+
+    /// Public address to hand out for the RDS endpoint `address`. The identifier of an instance or
+    /// cluster is the first label of its endpoint
+    pub fn public_address(&self, address: &str) -> String {
+        match &self.rds_public_host {
+            Some(template) => {
+                let identifier = address.split('.').next().unwrap_or(address);
+
+                template.replace(INSTANCE_PLACEHOLDER, identifier)
+            }
+            None => address.to_string(),
+        }
+    }
+
+    /// Make sure `class` is big enough for `engine`
+    pub fn check_instance_class(&self, engine: &str, class: &InstanceClass) -> Result<(), Error> {
+        match self.min_instance_classes.get(engine) {
+            Some(minimum) if !class.is_at_least(minimum) => Err(Error::InstanceClassTooSmall {
+                engine: engine.to_string(),
+                class: class.to_string(),
+                minimum: minimum.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn min_instance_class() {
+        let config = Config {
+            min_instance_classes: HashMap::from([(
+                "mysql".to_string(),
+                "db.t4g.small".parse().unwrap(),
+            )]),
+            ..Default::default()
+        };
+        let micro = "db.t4g.micro".parse().unwrap();
+        let medium = "db.t3.medium".parse().unwrap();
+
+        assert!(matches!(
+            config.check_instance_class("mysql", &micro),
+            Err(Error::InstanceClassTooSmall { .. })
+        ));
+        assert!(config.check_instance_class("mysql", &medium).is_ok());
+        assert!(config.check_instance_class("postgres", &micro).is_ok());
+    }
+
+    #[test]
+    fn connections() {
+        assert!(Config::default().validate().is_ok());
+
+        let config = Config {
+            min_connections: 13,
+            ..Default::default()
+        };
+
+        assert!(matches!(config.validate(), Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn allocated_storage() {
+        let config = Config {
+            allocated_storage: 0,
+            ..Default::default()
+        };
+
+        assert!(matches!(config.validate(), Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn storage_full_increase() {
+        let config = Config {
+            storage_full_increase: Some(5),
+            ..Default::default()
+        };
+
+        assert!(matches!(config.validate(), Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn rds_poll_intervals() {
+        let config = Config {
+            rds_poll_initial_interval: Duration::from_millis(50),
+            rds_poll_max_interval: Duration::from_millis(200),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        let config = Config {
+            rds_poll_initial_interval: Duration::ZERO,
+            ..Default::default()
+        };
+        assert!(matches!(config.validate(), Err(Error::InvalidConfig(_))));
+
+        let config = Config {
+            rds_poll_initial_interval: Duration::from_secs(20),
+            ..Default::default()
+        };
+        assert!(matches!(config.validate(), Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn aws_timeouts() {
+        let config = Config {
+            aws_call_timeout: Duration::from_secs(600),
+            aws_attempt_timeout: Duration::from_secs(200),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        let api = config.aws_timeouts().api_timeouts();
+        assert_eq!(api.call_timeout(), TriState::Set(Duration::from_secs(600)));
+        assert_eq!(
+            api.call_attempt_timeout(),
+            TriState::Set(Duration::from_secs(200))
+        );
+
+        let config = Config {
+            aws_attempt_timeout: Duration::from_secs(600),
+            ..Default::default()
+        };
+        assert!(matches!(config.validate(), Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn public_address() {
+        let endpoint = "shop-postgres.c9akciq32.eu-west-2.rds.amazonaws.com";
+        assert_eq!(Config::default().public_address(endpoint), endpoint);
+
+        let config = Config {
+            rds_public_host: Some("{instance}.db.example.com".to_string()),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.public_address(endpoint),
+            "shop-postgres.db.example.com"
+        );
+
+        // Everything can go through a single proxy
+        let config = Config {
+            rds_public_host: Some("proxy.example.com".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config.public_address(endpoint), "proxy.example.com");
+
+        for template in ["", "{instance}.db example.com", "{project}.db.example.com"] {
+            let config = Config {
+                rds_public_host: Some(template.to_string()),
+                ..Default::default()
+            };
+            assert!(
+                matches!(config.validate(), Err(Error::InvalidConfig(_))),
+                "{template}"
+            );
+        }
+    }
+
+    #[test]
+    fn provision_parallelism() {
+        let config = Config {
+            provision_parallelism: 0,
+            ..Default::default()
+        };
+
+        assert!(matches!(config.validate(), Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn prefixes() {
+        let config = Config {
+            role_prefix: "staging-user-".to_string(),
+            db_prefix: "staging_db_".to_string(),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        for prefix in ["", "-user", "user\"", "User-"] {
+            let config = Config {
+                role_prefix: prefix.to_string(),
+                ..Default::default()
+            };
+            assert!(
+                matches!(config.validate(), Err(Error::InvalidConfig(_))),
+                "{prefix}"
+            );
+        }
+    }
+
+    #[test]
+    fn sslmode() {
+        let config = Config {
+            sslmode: "verify-full".to_string(),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        let config = Config {
+            sslmode: "always".to_string(),
+            ..Default::default()
+        };
+        assert!(matches!(config.validate(), Err(Error::InvalidConfig(_))));
+    }
+}
@@ -1,30 +1,216 @@
-use aws_sdk_rds::{
-    error::{CreateDBInstanceError, DescribeDBInstancesError},
-    types::SdkError,
-};
+use shuttle_common::database::MasterUsernameError;
 use thiserror::Error;
 use tonic::Status;
-use tracing::error;
+use tracing::{error, warn};
+
+use crate::{rds::RdsError, secrets::SecretsError};
 
 #[derive(Error, Debug)]
 pub enum Error {
-    #[error("failed to create role")]
-    CreateRole(String),
+    #[error("failed to create role '{role}' for project '{project}': {reason}")]
+    CreateRole {
+        project: String,
+        role: String,
+        reason: String,
+    },
+
+    #[error("failed to update role '{role}' for project '{project}': {reason}")]
+    UpdateRole {
+        project: String,
+        role: String,
+        reason: String,
+    },
+
+    #[error("failed to create DB '{database}' for project '{project}': {reason}")]
+    CreateDB {
+        project: String,
+        database: String,
+        reason: String,
+    },
+
+    #[error("failed to create extension '{extension}' in DB '{database}': {reason}")]
+    CreateExtension {
+        database: String,
+        extension: String,
+        reason: String,
+    },
+
+    #[error("extension '{extension}' cannot be enabled, expected one of {allowed}")]
+    ExtensionNotAllowed { extension: String, allowed: String },
 
-    #[error("failed to update role")]
-    UpdateRole(String),
+    #[error("'{name}' is longer than the {max} bytes Postgres allows in a name")]
+    NameTooLong { name: String, max: usize },
 
-    #[error("failed to create DB")]
-    CreateDB(String),
+    #[error("failed to create schema: {0}")]
+    CreateSchema(String),
+
+    #[error("failed to grant privileges to role: {0}")]
+    GrantRole(String),
+
+    #[error("failed to delete role: {0}")]
+    DeleteRole(String),
+
+    #[error("failed to delete DB: {0}")]
+    DeleteDB(String),
 
     #[error("unexpected error")]
     Unexpected(#[from] sqlx::Error),
 
-    #[error("failed to create RDS instance")]
-    CreateRDSInstance(#[from] SdkError<CreateDBInstanceError>),
+    #[error("failed to manage shared Redis")]
+    Redis(#[from] redis::RedisError),
+
+    #[error("request has no database type")]
+    MissingDatabaseType,
+
+    #[error("RDS request has no engine")]
+    MissingEngine,
+
+    #[error("database '{0}' does not exist")]
+    DatabaseNotFound(String),
+
+    #[error("credentials of database '{0}' are not stored, rotate them to get new ones")]
+    CredentialsNotStored(String),
+
+    #[error("shared Redis is not configured on this provisioner")]
+    RedisNotConfigured,
+
+    #[error("{0}{}", request_id_suffix(.0))]
+    Rds(#[from] RdsError),
+
+    #[error("failed to store password in Secrets Manager")]
+    Secrets(#[from] SecretsError),
+
+    #[error("RDS instance '{0}' has deletion protection enabled, disable it before deleting the instance")]
+    DeletionProtected(String),
+
+    #[error("RDS instance '{instance}' is in the '{status}' state and needs manual intervention")]
+    InstanceFailed { instance: String, status: String },
+
+    #[error("RDS instance '{instance}' failed to create and ended up in the '{status}' state, it needs to be cleaned up manually")]
+    InstanceCreationFailed { instance: String, status: String },
+
+    #[error("the deadline of the request passed before it was done")]
+    DeadlineExceeded,
+
+    #[error("timed out waiting for RDS instance '{instance}' to go from '{status}' to '{wanted}'")]
+    WaitTimedOut {
+        instance: String,
+        status: String,
+        wanted: String,
+    },
+
+    #[error("RDS instance '{instance}' has run out of free space on its {allocated_storage} GiB of storage and is read-only, its storage has to be increased")]
+    StorageFull {
+        instance: String,
+        allocated_storage: i32,
+    },
+
+    #[error("RDS instance '{0}' has automated backups turned off, which read replicas need")]
+    ReadReplicaNeedsBackups(String),
+
+    #[error("subnet group '{subnet_group}' spans {zones} availability zone(s), but multi-AZ needs at least 2")]
+    InsufficientAvailabilityZones { subnet_group: String, zones: usize },
+
+    #[error("availability zone '{0}' cannot be picked for a multi-AZ instance")]
+    AvailabilityZoneWithMultiAz(String),
+
+    #[error(
+        "Aurora has no engine compatible with '{0}', only postgres and mysql can run on Aurora"
+    )]
+    AuroraNotSupported(String),
+
+    #[error(
+        "invalid project name '{0}', it can only contain lowercase letters, numbers and dashes"
+    )]
+    InvalidProjectName(String),
+
+    #[error("invalid role suffix '{0}', it has to be unique within the request and can only contain lowercase letters, numbers and underscores")]
+    InvalidRoleSuffix(String),
+
+    #[error("invalid database name '{0}', it has to start with a lowercase letter and can only contain lowercase letters, numbers, dashes and underscores")]
+    InvalidDatabaseName(String),
+
+    #[error("invalid database name '{name}', names starting with '{prefix}' are reserved for the default databases of projects")]
+    ReservedDatabaseName { name: String, prefix: String },
+
+    #[error("database '{0}' belongs to another project")]
+    ForeignDatabase(String),
+
+    #[error("unknown {setting} '{value}', it has to be one the shared Postgres knows")]
+    UnknownLocale { setting: String, value: String },
+
+    #[error(transparent)]
+    InvalidMasterUsername(#[from] MasterUsernameError),
+
+    #[error("RDS instance '{instance}' is a '{current}', set allow_resize to change it to '{requested}'")]
+    ResizeNotAllowed {
+        instance: String,
+        current: String,
+        requested: String,
+    },
+
+    #[error("project '{project}' already has the maximum of {limit} {kind}(s)")]
+    QuotaExceeded {
+        project: String,
+        kind: &'static str,
+        limit: usize,
+    },
 
-    #[error("failed to get description of RDS instance")]
-    DescribeRDSInstance(#[from] SdkError<DescribeDBInstancesError>),
+    #[error("invalid parameter group name '{0}', it has to start with a letter and can only contain letters, numbers and single dashes")]
+    InvalidParameterGroup(String),
+
+    #[error("instance class '{class}' does not support {options}")]
+    UnsupportedOptions { class: String, options: String },
+
+    #[error("cannot enable Performance Insights: {0}")]
+    InvalidPerformanceInsights(String),
+
+    #[error("invalid storage settings: {0}")]
+    InvalidStorage(String),
+
+    #[error("invalid window '{window}', expected the format {expected}")]
+    InvalidWindow {
+        window: String,
+        expected: &'static str,
+    },
+
+    #[error("{engine} has no '{log}' log to export to CloudWatch, expected one of {supported}")]
+    InvalidLogExport {
+        log: String,
+        engine: String,
+        supported: String,
+    },
+
+    #[error("DB snapshot '{0}' does not exist")]
+    SnapshotNotFound(String),
+
+    #[error(
+        "DB snapshot '{snapshot}' is of a {engine} database and cannot be restored as {requested}"
+    )]
+    SnapshotEngineMismatch {
+        snapshot: String,
+        engine: String,
+        requested: String,
+    },
+
+    #[error("shared Postgres is read-only, it may be a replica or in recovery: {0}")]
+    DatabaseReadOnly(String),
+
+    #[error("unknown environment '{environment}', expected one of: {known}")]
+    UnknownEnvironment { environment: String, known: String },
+
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+
+    #[error("invalid instance class: {0}")]
+    InvalidInstanceClass(String),
+
+    #[error("instance class '{class}' is too small for {engine}, use at least '{minimum}'")]
+    InstanceClassTooSmall {
+        engine: String,
+        class: String,
+        minimum: String,
+    },
 
     #[error["plain error"]]
     Plain(String),
@@ -32,9 +218,201 @@ pub enum Error {
 
 unsafe impl Send for Error {}
 
+impl Error {
+    /// ID of the failed AWS request behind this error, if there is one
+    pub fn aws_request_id(&self) -> Option<&str> {
+        match self {
+            Error::Rds(error) => error.request_id(),
+            _ => None,
+        }
+    }
+
+    /// Whether the error came from AWS, Postgres or Redis being unreachable or overloaded for a
+    /// moment, so the request can be made again as is
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Rds(error) => error.is_retryable(),
+            Error::Secrets(error) => error.is_retryable(),
+            Error::Unexpected(error) => matches!(
+                error,
+                sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed
+            ),
+            Error::Redis(error) => {
+                error.is_io_error() || error.is_connection_refusal() || error.is_timeout()
+            }
+            _ => false,
+        }
+    }
+}
+
+fn request_id_suffix(error: &RdsError) -> String {
+    match error.request_id() {
+        Some(request_id) => format!(" (AWS request ID: {request_id})"),
+        None => String::new(),
+    }
+}
+
 impl From<Error> for Status {
     fn from(err: Error) -> Self {
-        error!(error = &err as &dyn std::error::Error, "provision failed");
-        Status::internal("failed to provision a database")
+        match err {
+            Error::InvalidProjectName(_)
+            | Error::MissingDatabaseType
+            | Error::MissingEngine
+            | Error::InvalidDatabaseName(_)
+            | Error::ReservedDatabaseName { .. }
+            | Error::InvalidRoleSuffix(_)
+            | Error::NameTooLong { .. }
+            | Error::ExtensionNotAllowed { .. }
+            | Error::UnknownLocale { .. }
+            | Error::InvalidMasterUsername(_)
+            | Error::InvalidParameterGroup(_)
+            | Error::InvalidPerformanceInsights(_)
+            | Error::UnsupportedOptions { .. }
+            | Error::InvalidStorage(_)
+            | Error::InvalidWindow { .. }
+            | Error::InvalidLogExport { .. }
+            | Error::UnknownEnvironment { .. }
+            | Error::InvalidInstanceClass(_)
+            | Error::InstanceClassTooSmall { .. }
+            | Error::SnapshotEngineMismatch { .. }
+            | Error::AvailabilityZoneWithMultiAz(_)
+            | Error::AuroraNotSupported(_) => Status::invalid_argument(err.to_string()),
+            Error::InsufficientAvailabilityZones { .. }
+            | Error::RedisNotConfigured
+            | Error::DeletionProtected(_)
+            | Error::StorageFull { .. }
+            | Error::ReadReplicaNeedsBackups(_)
+            | Error::ResizeNotAllowed { .. }
+            | Error::ForeignDatabase(_) => Status::failed_precondition(err.to_string()),
+            Error::QuotaExceeded { .. } => Status::resource_exhausted(err.to_string()),
+            Error::DatabaseReadOnly(_) => {
+                warn!(
+                    error = &err as &dyn std::error::Error,
+                    "shared Postgres is read-only"
+                );
+                Status::unavailable("shared Postgres is read-only, try again against the primary")
+            }
+            Error::SnapshotNotFound(_)
+            | Error::DatabaseNotFound(_)
+            | Error::CredentialsNotStored(_) => Status::not_found(err.to_string()),
+            Error::WaitTimedOut { .. } | Error::DeadlineExceeded => {
+                warn!(
+                    error = &err as &dyn std::error::Error,
+                    "provision timed out"
+                );
+                Status::deadline_exceeded(err.to_string())
+            }
+            Error::InstanceFailed { .. } | Error::InstanceCreationFailed { .. } => {
+                error!(error = &err as &dyn std::error::Error, "provision failed");
+                Status::internal(err.to_string())
+            }
+            _ if err.is_transient() => {
+                warn!(
+                    error = &err as &dyn std::error::Error,
+                    aws_request_id = err.aws_request_id(),
+                    "provision failed on a transient error"
+                );
+                Status::unavailable("failed to provision a database, try again")
+            }
+            _ => {
+                error!(
+                    error = &err as &dyn std::error::Error,
+                    aws_request_id = err.aws_request_id(),
+                    "provision failed"
+                );
+                Status::internal("failed to provision a database")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_rds::types::SdkError;
+    use tonic::Code;
+
+    use super::*;
+
+    fn code(error: Error) -> Code {
+        Status::from(error).code()
+    }
+
+    #[test]
+    fn status_codes() {
+        assert_eq!(
+            code(Error::InvalidProjectName("Bad".to_string())),
+            Code::InvalidArgument
+        );
+        assert_eq!(
+            code(Error::QuotaExceeded {
+                project: "project".to_string(),
+                kind: "shared database",
+                limit: 1,
+            }),
+            Code::ResourceExhausted
+        );
+        assert_eq!(
+            code(Error::WaitTimedOut {
+                instance: "project-postgres".to_string(),
+                status: "creating".to_string(),
+                wanted: "available".to_string(),
+            }),
+            Code::DeadlineExceeded
+        );
+        assert_eq!(
+            code(Error::SnapshotNotFound("snapshot".to_string())),
+            Code::NotFound
+        );
+        assert_eq!(
+            code(Error::DeletionProtected("project-postgres".to_string())),
+            Code::FailedPrecondition
+        );
+        assert_eq!(
+            code(Error::DatabaseReadOnly(
+                "cannot execute CREATE ROLE in a read-only transaction".to_string()
+            )),
+            Code::Unavailable
+        );
+        assert_eq!(
+            code(Error::CreateRole {
+                project: "project".to_string(),
+                role: "user-project".to_string(),
+                reason: "permission denied".to_string(),
+            }),
+            Code::Internal
+        );
+    }
+
+    #[test]
+    fn shared_db_context() {
+        let error = Error::CreateDB {
+            project: "foo".to_string(),
+            database: "db-foo".to_string(),
+            reason: "permission denied".to_string(),
+        };
+
+        assert_eq!(
+            error.to_string(),
+            "failed to create DB 'db-foo' for project 'foo': permission denied"
+        );
+    }
+
+    #[test]
+    fn transient_status_codes() {
+        let timeout = RdsError::Describe(SdkError::TimeoutError("timed out".into()));
+        assert_eq!(code(Error::Rds(timeout)), Code::Unavailable);
+        assert_eq!(code(Error::Rds(RdsError::InstanceNotFound)), Code::Internal);
+
+        assert_eq!(
+            code(Error::Unexpected(sqlx::Error::PoolTimedOut)),
+            Code::Unavailable
+        );
+        assert_eq!(
+            code(Error::Unexpected(sqlx::Error::RowNotFound)),
+            Code::Internal
+        );
+
+        let refused = redis::RedisError::from((redis::ErrorKind::IoError, "connection refused"));
+        assert_eq!(code(Error::Redis(refused)), Code::Unavailable);
     }
 }
@@ -0,0 +1,181 @@
+use std::{fmt::Display, str::FromStr};
+
+/// An RDS instance class like `db.t4g.micro`, split into its family and size
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InstanceClass {
+    family: String,
+    size: String,
+}
+
+impl InstanceClass {
+    pub fn family(&self) -> &str {
+        &self.family
+    }
+
+    pub fn size(&self) -> &str {
+        &self.size
+    }
+
+    /// Relative capacity of this class's size, used to compare classes across families
+    fn rank(&self) -> u32 {
+        size_rank(&self.size).expect("size to be validated on parse")
+    }
+
+    /// Whether AWS lets instances of this class use `feature`
+    pub fn supports(&self, feature: Feature) -> bool {
+        self.rank() >= size_rank(feature.min_size()).expect("min size to be a valid size")
+    }
+
+    /// Check if this class is at least as big as `other`
+    pub fn is_at_least(&self, other: &InstanceClass) -> bool {
+        self.rank() >= other.rank()
+    }
+}
+
+/// Options of an RDS request which AWS only supports on some instance classes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Feature {
+    PerformanceInsights,
+    MultiAz,
+    IamAuth,
+    Io1Storage,
+}
+
+/// Smallest size of any family which supports each feature. AWS rejects create calls asking for
+/// a feature on anything smaller, but only after a slow round trip
+const CAPABILITIES: [(Feature, &str); 4] = [
+    (Feature::PerformanceInsights, "medium"),
+    (Feature::MultiAz, "small"),
+    (Feature::IamAuth, "small"),
+    (Feature::Io1Storage, "small"),
+];
+
+impl Feature {
+    /// Smallest size supporting this feature
+    pub fn min_size(&self) -> &'static str {
+        CAPABILITIES
+            .iter()
+            .find(|(feature, _)| feature == self)
+            .map(|(_, size)| *size)
+            .expect("every feature to have a min size")
+    }
+}
+
+impl Display for Feature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PerformanceInsights => write!(f, "Performance Insights"),
+            Self::MultiAz => write!(f, "multi-AZ"),
+            Self::IamAuth => write!(f, "IAM authentication"),
+            Self::Io1Storage => write!(f, "io1 storage"),
+        }
+    }
+}
+
+impl FromStr for InstanceClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('.');
+
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some("db"), Some(family), Some(size), None)
+                if !family.is_empty() && size_rank(size).is_some() =>
+            {
+                Ok(Self {
+                    family: family.to_string(),
+                    size: size.to_string(),
+                })
+            }
+            _ => Err(format!(
+                "'{s}' is not a valid instance class, expected something like 'db.t4g.micro'"
+            )),
+        }
+    }
+}
+
+impl Display for InstanceClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "db.{}.{}", self.family, self.size)
+    }
+}
+
+fn size_rank(size: &str) -> Option<u32> {
+    match size {
+        "micro" => Some(1),
+        "small" => Some(2),
+        "medium" => Some(4),
+        "large" => Some(8),
+        "xlarge" => Some(16),
+        _ => size
+            .strip_suffix("xlarge")
+            .and_then(|multiple| multiple.parse::<u32>().ok())
+            .filter(|multiple| *multiple > 0)
+            .map(|multiple| multiple * 16),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let class: InstanceClass = "db.t4g.micro".parse().unwrap();
+
+        assert_eq!(class.family(), "t4g");
+        assert_eq!(class.size(), "micro");
+        assert_eq!(class.to_string(), "db.t4g.micro");
+
+        for invalid in [
+            "t4g.micro",
+            "db.t4g",
+            "db..micro",
+            "db.t4g.huge",
+            "db.m5.0xlarge",
+        ] {
+            assert!(
+                invalid.parse::<InstanceClass>().is_err(),
+                "{invalid:?} was ok"
+            );
+        }
+    }
+
+    #[test]
+    fn ordering() {
+        let micro: InstanceClass = "db.t4g.micro".parse().unwrap();
+        let large: InstanceClass = "db.m5.large".parse().unwrap();
+        let big: InstanceClass = "db.r5.12xlarge".parse().unwrap();
+
+        assert!(large.is_at_least(&micro));
+        assert!(big.is_at_least(&large));
+        assert!(micro.is_at_least(&micro));
+        assert!(!micro.is_at_least(&large));
+    }
+
+    #[test]
+    fn capabilities() {
+        for class in ["db.t4g.micro", "db.t3.small"] {
+            let class: InstanceClass = class.parse().unwrap();
+            assert!(
+                !class.supports(Feature::PerformanceInsights),
+                "{class} was ok"
+            );
+        }
+
+        for class in ["db.t4g.medium", "db.m5.large", "db.r5.2xlarge"] {
+            let class: InstanceClass = class.parse().unwrap();
+            assert!(
+                class.supports(Feature::PerformanceInsights),
+                "{class} was err"
+            );
+        }
+
+        let micro: InstanceClass = "db.t3.micro".parse().unwrap();
+        let small: InstanceClass = "db.t3.small".parse().unwrap();
+        for feature in [Feature::MultiAz, Feature::IamAuth, Feature::Io1Storage] {
+            assert!(!micro.supports(feature), "{feature} was ok");
+            assert!(small.supports(feature), "{feature} was err");
+        }
+    }
+}
@@ -1,289 +1,4110 @@
-use std::time::Duration;
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-pub use args::Args;
-use aws_config::timeout;
-use aws_sdk_rds::{error::ModifyDBInstanceErrorKind, model::DbInstance, types::SdkError, Client};
-use aws_smithy_types::tristate::TriState;
+pub use admin::serve_admin;
+pub use args::{Args, LogFormat};
+use aws_sdk_rds::{
+    model::{DbCluster, DbInstance, DbSubnetGroup, Tag},
+    Endpoint,
+};
+use backoff::Backoff;
+pub use config::{Config, Network};
 pub use error::Error;
+use futures::{stream, StreamExt};
+pub use instance_class::{Feature, InstanceClass};
 use rand::Rng;
+pub use rds::{
+    CreateCluster, CreateClusterInstance, CreateInstance, CreateReadReplica, DeleteCluster,
+    DeleteInstance, ModifyCluster, ModifyInstance, RdsClient, RdsError, RestoreInstance,
+};
+pub use reconcile::{Cleanup, Inventory, TaggedInstance};
+pub use secrets::{PutSecret, SecretsClient, SecretsError};
+pub use server::ServerBuilder;
+pub use shared::{
+    check_db_locale, grant_shared_db, grant_shared_role, shared_db, shared_extensions, shared_role,
+    shared_schema, DbLocale, DbOutcome, Privilege, RoleOutcome,
+};
+use shuttle_common::database::{aws_rds_master_username, AwsRdsEngine};
 use shuttle_proto::provisioner::provisioner_server::Provisioner;
 pub use shuttle_proto::provisioner::provisioner_server::ProvisionerServer;
 use shuttle_proto::provisioner::{
-    aws_rds, database_request::DbType, AwsRds, DatabaseRequest, DatabaseResponse,
+    aws_rds, database_request::DbType, database_role, provision_result, AwsRds, DatabaseInfo,
+    DatabaseRequest, DatabaseResponse, DatabaseRole, DeleteDatabaseResponse,
+    DescribeDatabaseResponse, EngineInfo, ListDatabasesRequest, ListDatabasesResponse,
+    ProvisionDatabasesRequest, ProvisionDatabasesResponse, ProvisionError, ProvisionResult,
+    ProvisionSummary, RdsConfig, ReaderCredentials, RoleCredentials, Shared,
+    SupportedEnginesRequest, SupportedEnginesResponse,
+};
+use sqlx::{
+    postgres::{PgConnectOptions, PgPoolOptions},
+    ConnectOptions, Executor, PgPool,
+};
+pub use status::InstanceStatus;
+use tokio::{
+    sync::{Mutex as AsyncMutex, OwnedMutexGuard},
+    time::sleep,
 };
-use sqlx::{postgres::PgPoolOptions, PgPool};
-use tokio::time::sleep;
 use tonic::{Request, Response, Status};
-use tracing::{debug, info};
+use tracing::{debug, error, field, info, warn, Span};
+use uuid::Uuid;
+use webhook::Webhook;
 
+mod admin;
 mod args;
+mod backoff;
+mod config;
 mod error;
+mod instance_class;
+mod rds;
+mod reconcile;
+mod secrets;
+mod server;
+mod shared;
+mod status;
+mod webhook;
+
+/// Name AWS gives the subnet group of the default VPC
+const DEFAULT_VPC_SUBNET_GROUP: &str = "default";
+/// Bundle with the certificates of every RDS CA in every region
+const RDS_CA_BUNDLE_URL: &str = "https://truststore.pki.rds.amazonaws.com/global/global-bundle.pem";
+/// Tag holding the project an RDS instance or secret was created for
+const PROJECT_TAG: &str = "shuttle-project";
+/// Prefix of the names of the secrets holding the passwords of RDS instances
+const PASSWORD_SECRET_PREFIX: &str = "shuttle/rds/";
+/// Prefix of the names of the secrets holding the passwords of shared Postgres roles
+const SHARED_PASSWORD_SECRET_PREFIX: &str = "shuttle/shared/";
+
+/// Extensions a shared database can ask for. They ship with Postgres and cannot be used to get
+/// around the limits of a project's role
+const ALLOWED_EXTENSIONS: &[&str] = &[
+    "btree_gin",
+    "btree_gist",
+    "citext",
+    "cube",
+    "fuzzystrmatch",
+    "hstore",
+    "intarray",
+    "ltree",
+    "pg_trgm",
+    "pgcrypto",
+    "tablefunc",
+    "unaccent",
+    "uuid-ossp",
+];
 
-const AWS_RDS_CLASS: &str = "db.t4g.micro";
-const MASTER_USERNAME: &str = "master";
-const RDS_SUBNET_GROUP: &str = "shuttle_rds";
+/// Smallest class which every Aurora engine version can run on
+const AURORA_DEFAULT_INSTANCE_CLASS: &str = "db.t4g.medium";
+/// Backoff between attempts of a create or modify call which failed with a retryable error
+const RETRY_BACKOFF: Backoff = Backoff {
+    initial: Duration::from_millis(500),
+    max: Duration::from_secs(8),
+};
+/// Attempts a create or modify call gets before its error is returned
+const RETRY_ATTEMPTS: usize = 4;
+/// Backoff between checks of whether the shared Postgres is reachable at startup
+const READY_BACKOFF: Backoff = Backoff {
+    initial: Duration::from_millis(250),
+    max: Duration::from_secs(5),
+};
+/// How long a modification gets to show up in the status of an instance or cluster. Quick ones,
+/// like most password resets, can be over before they are ever seen
+const MODIFICATION_GRACE: Duration = Duration::from_secs(30);
+/// Longest identifier Postgres keeps. Longer ones are silently truncated, so lookups by the full
+/// name would never find what was created
+const MAX_IDENTIFIER_LEN: usize = 63;
+/// Longest identifier RDS accepts for an instance or cluster. This only happens to match the
+/// Postgres limit: RDS names are also used for MySQL and MariaDB instances, and are shortened
+/// with a hash where Postgres names are rejected, so the two are kept apart
+const MAX_RDS_IDENTIFIER_LEN: usize = 63;
+/// Suffix of the read-only role of a shared DB, which extra roles cannot take
+const READER_SUFFIX: &str = "reader";
+/// Default ports of the engines, which clients connect to unless told otherwise
+const POSTGRES_PORT: u16 = 5432;
+const MYSQL_PORT: u16 = 3306;
+const REDIS_PORT: u16 = 6379;
 
 pub struct MyProvisioner {
     pool: PgPool,
-    rds_client: aws_sdk_rds::Client,
+    pg_connect_options: PgConnectOptions,
+    redis_client: Option<redis::Client>,
+    rds_client: Box<dyn RdsClient>,
+    secrets_client: Box<dyn SecretsClient>,
+    /// Locks held while working on an RDS instance, keyed on the instance name
+    instance_locks: InstanceLocks,
+    /// When creating RDS instances was started, keyed on the instance name. AWS only reports the
+    /// create time of an instance once it is done creating
+    creation_starts: Mutex<HashMap<String, SystemTime>>,
+    shared_counts: Mutex<SharedCounts>,
+    webhook: Option<Webhook>,
     fqdn: String,
     internal_address: String,
+    config: Config,
+}
+
+/// Outcomes of successful shared database provisions
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SharedCounts {
+    pub roles_created: u64,
+    pub passwords_cycled: u64,
+    pub databases_created: u64,
+    pub databases_existing: u64,
 }
 
 impl MyProvisioner {
-    pub async fn new(db_uri: &str, fqdn: String, internal_address: String) -> sqlx::Result<Self> {
+    pub async fn new(db_uri: &str, fqdn: String, internal_address: String) -> Result<Self, Error> {
+        Self::with_config(db_uri, fqdn, internal_address, Config::default()).await
+    }
+
+    pub async fn with_config(
+        db_uri: &str,
+        fqdn: String,
+        internal_address: String,
+        config: Config,
+    ) -> Result<Self, Error> {
+        config.validate()?;
+
+        // Role statements carry the generated passwords, so they must not end up in the logs
+        let mut connect_options: PgConnectOptions = db_uri.parse()?;
+        connect_options.disable_statement_logging();
+
+        // A hung statement would otherwise hold on to its connection for good, and a few of those
+        // can starve the whole pool
+        let set_timeouts = format!(
+            "SET statement_timeout = {}; SET lock_timeout = {}",
+            config.statement_timeout.as_millis(),
+            config.lock_timeout.as_millis()
+        );
+
         let pool = PgPoolOptions::new()
-            .min_connections(4)
-            .max_connections(12)
-            .connect_timeout(Duration::from_secs(60))
-            .connect_lazy(db_uri)?;
+            .min_connections(config.min_connections)
+            .max_connections(config.max_connections)
+            .connect_timeout(config.connect_timeout)
+            .idle_timeout(config.idle_timeout)
+            .max_lifetime(config.max_lifetime)
+            .after_connect(move |connection| {
+                let set_timeouts = set_timeouts.clone();
+
+                Box::pin(async move {
+                    connection.execute(set_timeouts.as_str()).await?;
 
-        // Default timeout is too long so lowering it
-        let api_timeout_config = timeout::Api::new()
-            .with_call_timeout(TriState::Set(Duration::from_secs(120)))
-            .with_call_attempt_timeout(TriState::Set(Duration::from_secs(120)));
-        let timeout_config = timeout::Config::new().with_api_timeouts(api_timeout_config);
+                    Ok(())
+                })
+            })
+            .connect_lazy_with(connect_options.clone());
 
         let aws_config = aws_config::from_env()
-            .timeout_config(timeout_config)
+            .timeout_config(config.aws_timeouts())
             .load()
             .await;
 
-        let rds_client = aws_sdk_rds::Client::new(&aws_config);
+        let mut rds_config = aws_sdk_rds::config::Builder::from(&aws_config);
+        if let Some(endpoint_url) = &config.aws_endpoint_url {
+            let uri = endpoint_url.parse().map_err(|error| {
+                Error::InvalidConfig(format!("invalid AWS endpoint '{endpoint_url}': {error}"))
+            })?;
+            rds_config = rds_config.endpoint_resolver(Endpoint::immutable(uri));
+        }
+
+        let rds_client = aws_sdk_rds::Client::from_conf(rds_config.build());
+
+        let mut secrets_config = aws_sdk_secretsmanager::config::Builder::from(&aws_config);
+        if let Some(endpoint_url) = &config.aws_endpoint_url {
+            let uri = endpoint_url.parse().map_err(|error| {
+                Error::InvalidConfig(format!("invalid AWS endpoint '{endpoint_url}': {error}"))
+            })?;
+            secrets_config =
+                secrets_config.endpoint_resolver(aws_sdk_secretsmanager::Endpoint::immutable(uri));
+        }
+
+        let secrets_client = aws_sdk_secretsmanager::Client::from_conf(secrets_config.build());
+
+        let webhook = config
+            .webhook_url
+            .as_deref()
+            .map(Webhook::new)
+            .transpose()?;
+
+        let redis_client = config
+            .shared_redis_uri
+            .as_deref()
+            .map(redis::Client::open)
+            .transpose()?;
 
         Ok(Self {
             pool,
-            rds_client,
+            pg_connect_options: connect_options,
+            redis_client,
+            rds_client: Box::new(rds_client),
+            secrets_client: Box::new(secrets_client),
+            instance_locks: Default::default(),
+            creation_starts: Default::default(),
+            shared_counts: Default::default(),
+            webhook,
             fqdn,
             internal_address,
+            config,
         })
     }
 
-    pub async fn request_shared_db(&self, project_name: &str) -> Result<DatabaseResponse, Error> {
-        let (username, password) = self.shared_role(project_name).await?;
-        let database_name = self.shared_db(project_name, &username).await?;
+    /// Make RDS calls through `rds_client` instead of the AWS client
+    pub fn with_rds_client(self, rds_client: impl RdsClient + 'static) -> Self {
+        Self {
+            rds_client: Box::new(rds_client),
+            ..self
+        }
+    }
+
+    /// Make Secrets Manager calls through `secrets_client` instead of the AWS client
+    pub fn with_secrets_client(self, secrets_client: impl SecretsClient + 'static) -> Self {
+        Self {
+            secrets_client: Box::new(secrets_client),
+            ..self
+        }
+    }
+
+    /// Wait for the shared Postgres to answer queries, giving up once `timeout` has passed. The
+    /// pool only connects when it is first used, so this is how to find out it is reachable
+    /// before taking requests
+    pub async fn wait_for_shared_pg(&self, timeout: Duration) -> Result<(), Error> {
+        let mut delays = READY_BACKOFF.delays();
+        let start = tokio::time::Instant::now();
+
+        loop {
+            match sqlx::query("SELECT 1").execute(&self.pool).await {
+                Ok(_) => return Ok(()),
+                Err(error) if start.elapsed() < timeout => {
+                    let delay = delays.next().expect("delays to be endless");
+                    warn!(
+                        error = &error as &dyn std::error::Error,
+                        ?delay,
+                        "shared Postgres is not reachable yet"
+                    );
+
+                    sleep(delay).await;
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+
+    pub async fn request_shared_db(
+        &self,
+        project_name: &str,
+        options: &Shared,
+    ) -> Result<DatabaseResponse, Error> {
+        validate_project_name(project_name)?;
+        let database_name = shared_db_name(&self.config.db_prefix, project_name, options)?;
+        check_extensions(&options.extensions)?;
+        let locale = db_locale(options);
+        shared::check_db_locale(&self.pool, &locale).await?;
+        self.check_shared_db_quota(project_name, &database_name)
+            .await?;
+
+        let username = self.shared_role_name(project_name)?;
+        let extra_roles = extra_role_names(&username, &options.roles)?;
+        let (password, role) = shared::shared_role(
+            &self.pool,
+            project_name,
+            &username,
+            self.config.connection_limit,
+        )
+        .await?;
+
+        let database = async {
+            // The role is kept, so only its password changes. Only a database of the project
+            // itself can be dropped
+            if options.recreate {
+                self.check_db_owner(&database_name, &username).await?;
+                info!("recreating database");
+
+                shared::drop_shared_db(&self.pool, &database_name).await?;
+            }
+
+            self.shared_db(project_name, &database_name, &username, &locale)
+                .await
+        };
+
+        let database = match database.await {
+            Ok(database) => database,
+            Err(error) => {
+                // Only undo our own work so a retry starts clean. A role which already existed
+                // can still own other databases of the project
+                if role == RoleOutcome::Created {
+                    info!("dropping new user after failing to create its database");
+
+                    if let Err(drop_error) = shared::drop_shared_role(&self.pool, &username).await {
+                        error!(
+                            error = &drop_error as &dyn std::error::Error,
+                            "failed to drop new user"
+                        );
+                    }
+                }
+
+                return Err(error);
+            }
+        };
+
+        let created = database == DbOutcome::Created;
+        if created && !options.extensions.is_empty() {
+            shared::shared_extensions(
+                &self.pg_connect_options,
+                &database_name,
+                &options.extensions,
+            )
+            .await?;
+        }
+
+        if options.dedicated_schema {
+            shared::shared_schema(
+                &self.pool,
+                &self.pg_connect_options,
+                &database_name,
+                &username,
+                project_name,
+            )
+            .await?;
+        }
+
+        let reader = if options.reader {
+            Some(
+                self.shared_reader(project_name, &database_name, &username, options)
+                    .await?,
+            )
+        } else {
+            None
+        };
+
+        let mut roles = Vec::new();
+        for (extra_role, name) in options.roles.iter().zip(extra_roles) {
+            roles.push(
+                self.shared_extra_role(
+                    project_name,
+                    &database_name,
+                    &username,
+                    name,
+                    extra_role,
+                    options,
+                )
+                .await?,
+            );
+        }
+
+        self.count_shared(role, database);
+        let password_secret_arn = self
+            .store_shared_password(project_name, &username, &password)
+            .await?;
 
         Ok(DatabaseResponse {
+            engine_version: self.shared_server_version().await?,
             engine: "postgres".to_string(),
             username,
             password,
+            password_secret_arn,
             database_name,
             address_private: self.internal_address.clone(),
             address_public: self.fqdn.clone(),
-            port: "5432".to_string(),
+            port: POSTGRES_PORT.to_string(),
+            created,
+            sslmode: self.config.sslmode.clone(),
+            reader,
+            roles,
+            ..Default::default()
         })
     }
 
-    async fn shared_role(&self, project_name: &str) -> Result<(String, String), Error> {
-        let username = format!("user-{project_name}");
-        let password = generate_password();
+    /// Make sure the read-only role of a shared DB exists with a new password, and can read
+    /// everything the project's role puts in the database
+    async fn shared_reader(
+        &self,
+        project_name: &str,
+        database_name: &str,
+        owner: &str,
+        options: &Shared,
+    ) -> Result<ReaderCredentials, Error> {
+        let username = shared_reader_name(owner)?;
+        let (password, _) = shared::shared_role(
+            &self.pool,
+            project_name,
+            &username,
+            self.config.connection_limit,
+        )
+        .await?;
 
-        let matching_user = sqlx::query("SELECT rolname FROM pg_roles WHERE rolname = $1")
-            .bind(&username)
-            .fetch_optional(&self.pool)
+        shared::grant_shared_role(
+            &self.pool,
+            &self.pg_connect_options,
+            database_name,
+            owner,
+            &username,
+            Privilege::ReadOnly,
+            &shared_schemas(project_name, options),
+        )
+        .await?;
+
+        Ok(ReaderCredentials { username, password })
+    }
+
+    /// Make sure an extra role of a shared DB exists with a new password, and has exactly the
+    /// privilege it asks for now
+    async fn shared_extra_role(
+        &self,
+        project_name: &str,
+        database_name: &str,
+        owner: &str,
+        username: String,
+        role: &DatabaseRole,
+        options: &Shared,
+    ) -> Result<RoleCredentials, Error> {
+        let (password, _) = shared::shared_role(
+            &self.pool,
+            project_name,
+            &username,
+            self.config.connection_limit,
+        )
+        .await?;
+
+        let privilege = match role.privilege() {
+            database_role::Privilege::Owner => Privilege::Owner,
+            database_role::Privilege::Readwrite => Privilege::ReadWrite,
+            database_role::Privilege::Readonly => Privilege::ReadOnly,
+        };
+        shared::grant_shared_role(
+            &self.pool,
+            &self.pg_connect_options,
+            database_name,
+            owner,
+            &username,
+            privilege,
+            &shared_schemas(project_name, options),
+        )
+        .await?;
+
+        Ok(RoleCredentials { username, password })
+    }
+
+    /// Give the role of an existing shared DB a new password
+    pub async fn rotate_shared_db(
+        &self,
+        project_name: &str,
+        options: &Shared,
+    ) -> Result<DatabaseResponse, Error> {
+        validate_project_name(project_name)?;
+        let database_name = shared_db_name(&self.config.db_prefix, project_name, options)?;
+        let username = self.shared_role_name(project_name)?;
+        self.check_shared_db_exists(&database_name, &username)
             .await?;
 
-        if matching_user.is_none() {
-            info!("creating new user");
+        info!("rotating password of user");
+        let password = shared::rotate_role_password(&self.pool, project_name, &username).await?;
+        let password_secret_arn = self
+            .store_shared_password(project_name, &username, &password)
+            .await?;
 
-            // Binding does not work for identifiers
-            // https://stackoverflow.com/questions/63723236/sql-statement-to-create-role-fails-on-postgres-12-using-dapper
-            let create_role_query =
-                format!("CREATE ROLE \"{username}\" WITH LOGIN PASSWORD '{password}'");
-            sqlx::query(&create_role_query)
-                .execute(&self.pool)
-                .await
-                .map_err(|e| Error::CreateRole(e.to_string()))?;
-        } else {
-            info!("cycling password of user");
-
-            // Binding does not work for identifiers
-            // https://stackoverflow.com/questions/63723236/sql-statement-to-create-role-fails-on-postgres-12-using-dapper
-            let update_role_query =
-                format!("ALTER ROLE \"{username}\" WITH LOGIN PASSWORD '{password}'");
-            sqlx::query(&update_role_query)
-                .execute(&self.pool)
-                .await
-                .map_err(|e| Error::UpdateRole(e.to_string()))?;
+        Ok(DatabaseResponse {
+            engine_version: self.shared_server_version().await?,
+            engine: "postgres".to_string(),
+            username,
+            password,
+            password_secret_arn,
+            database_name,
+            address_private: self.internal_address.clone(),
+            address_public: self.fqdn.clone(),
+            port: POSTGRES_PORT.to_string(),
+            sslmode: self.config.sslmode.clone(),
+            ..Default::default()
+        })
+    }
+
+    /// Connection details of the shared DB of a project, without rotating its password. Only
+    /// works when the password is kept in a secret, whose ARN is handed out instead
+    pub async fn shared_db_connection_info(
+        &self,
+        project_name: &str,
+        options: &Shared,
+    ) -> Result<DatabaseResponse, Error> {
+        validate_project_name(project_name)?;
+        let database_name = shared_db_name(&self.config.db_prefix, project_name, options)?;
+        let username = self.shared_role_name(project_name)?;
+        self.check_shared_db_exists(&database_name, &username)
+            .await?;
+        self.check_db_owner(&database_name, &username).await?;
+
+        if !self.config.shared_password_secrets {
+            return Err(Error::CredentialsNotStored(database_name));
         }
 
-        Ok((username, password))
-    }
+        let name = shared_password_secret_name(&username);
+        let password_secret_arn = RETRY_BACKOFF
+            .retry(RETRY_ATTEMPTS, SecretsError::is_retryable, || {
+                self.secrets_client.secret_arn(&name)
+            })
+            .await?
+            .ok_or_else(|| Error::CredentialsNotStored(database_name.clone()))?;
 
-    async fn shared_db(&self, project_name: &str, username: &str) -> Result<String, Error> {
-        let database_name = format!("db-{project_name}");
+        Ok(DatabaseResponse {
+            engine_version: self.shared_server_version().await?,
+            engine: "postgres".to_string(),
+            username,
+            password_secret_arn,
+            database_name,
+            address_private: self.internal_address.clone(),
+            address_public: self.fqdn.clone(),
+            port: POSTGRES_PORT.to_string(),
+            sslmode: self.config.sslmode.clone(),
+            ..Default::default()
+        })
+    }
 
+    /// Make sure both the role and the database of a shared DB exist
+    async fn check_shared_db_exists(
+        &self,
+        database_name: &str,
+        username: &str,
+    ) -> Result<(), Error> {
+        let matching_user = sqlx::query("SELECT rolname FROM pg_roles WHERE rolname = $1")
+            .bind(username)
+            .fetch_optional(&self.pool)
+            .await?;
         let matching_db = sqlx::query("SELECT datname FROM pg_database WHERE datname = $1")
-            .bind(&database_name)
+            .bind(database_name)
             .fetch_optional(&self.pool)
             .await?;
 
-        if matching_db.is_none() {
-            info!("creating database");
+        if matching_user.is_none() || matching_db.is_none() {
+            return Err(Error::DatabaseNotFound(database_name.to_string()));
+        }
+
+        Ok(())
+    }
 
-            // Binding does not work for identifiers
-            // https://stackoverflow.com/questions/63723236/sql-statement-to-create-role-fails-on-postgres-12-using-dapper
-            let create_db_query = format!("CREATE DATABASE \"{database_name}\" OWNER '{username}'");
-            sqlx::query(&create_db_query)
-                .execute(&self.pool)
-                .await
-                .map_err(|e| Error::CreateDB(e.to_string()))?;
+    /// Put the password of the shared Postgres role `username` in Secrets Manager if it is
+    /// configured to keep them, returning the ARN of its secret
+    async fn store_shared_password(
+        &self,
+        project_name: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<String, Error> {
+        if !self.config.shared_password_secrets {
+            return Ok(String::new());
         }
 
-        Ok(database_name)
+        let put = PutSecret {
+            name: shared_password_secret_name(username),
+            value: password.to_string(),
+            project: project_name.to_string(),
+        };
+        let arn = RETRY_BACKOFF
+            .retry(RETRY_ATTEMPTS, SecretsError::is_retryable, || {
+                self.secrets_client.put_secret(put.clone())
+            })
+            .await?;
+
+        Ok(arn)
     }
 
-    async fn request_aws_rds(
+    /// Provision a logical cache on the shared Redis. The project gets an ACL user which can only
+    /// touch keys starting with the `key_prefix` of the response, and re-provisioning rotates its
+    /// password
+    pub async fn request_shared_redis(
         &self,
         project_name: &str,
-        engine: aws_rds::Engine,
     ) -> Result<DatabaseResponse, Error> {
-        let client = &self.rds_client;
+        validate_project_name(project_name)?;
 
-        let password = generate_password();
-        let instance_name = format!("{}-{}", project_name, engine);
+        let mut connection = self.redis_connection().await?;
+        let username = self.shared_redis_user(project_name);
 
-        debug!("trying to get AWS RDS instance: {instance_name}");
-        let instance = client
-            .modify_db_instance()
-            .db_instance_identifier(&instance_name)
-            .master_user_password(&password)
-            .send()
-            .await;
+        let created = !redis_user_exists(&mut connection, &username).await?;
+        if created {
+            info!("creating new redis user");
+        } else {
+            info!("cycling password of redis user");
+        }
 
-        match instance {
-            Ok(_) => {
-                wait_for_instance(client, &instance_name, "resetting-master-credentials").await?;
-            }
-            Err(SdkError::ServiceError { err, .. }) => {
-                if let ModifyDBInstanceErrorKind::DbInstanceNotFoundFault(_) = err.kind {
-                    debug!("creating new AWS RDS {instance_name}");
+        let password = set_redis_user(&mut connection, &username).await?;
 
-                    client
-                        .create_db_instance()
-                        .db_instance_identifier(&instance_name)
-                        .master_username(MASTER_USERNAME)
-                        .master_user_password(&password)
-                        .engine(engine.to_string())
-                        .db_instance_class(AWS_RDS_CLASS)
-                        .allocated_storage(20)
-                        .backup_retention_period(0) // Disable backups
-                        .publicly_accessible(true)
-                        .db_name(engine.to_string())
-                        .set_db_subnet_group_name(Some(RDS_SUBNET_GROUP.to_string()))
-                        .send()
-                        .await?
-                        .db_instance
-                        .expect("to be able to create instance");
+        Ok(self.shared_redis_response(username, password, created))
+    }
 
-                    wait_for_instance(client, &instance_name, "creating").await?;
-                } else {
-                    return Err(Error::Plain(format!(
-                        "got unexpected error from AWS RDS service: {}",
-                        err
-                    )));
-                }
-            }
-            Err(unexpected) => {
-                return Err(Error::Plain(format!(
-                    "got unexpected error from AWS during API call: {}",
-                    unexpected
-                )))
-            }
-        };
+    /// Give the ACL user of an existing shared Redis cache a new password
+    pub async fn rotate_shared_redis(&self, project_name: &str) -> Result<DatabaseResponse, Error> {
+        validate_project_name(project_name)?;
 
-        // Wait for up
-        let instance = wait_for_instance(client, &instance_name, "available").await?;
+        let mut connection = self.redis_connection().await?;
+        let username = self.shared_redis_user(project_name);
 
-        // TODO: find private IP somehow
-        let address = instance
-            .endpoint
-            .expect("instance to have an endpoint")
-            .address
-            .expect("endpoint to have an address");
+        if !redis_user_exists(&mut connection, &username).await? {
+            return Err(Error::DatabaseNotFound(username));
+        }
 
-        Ok(DatabaseResponse {
-            engine: engine.to_string(),
-            username: instance
-                .master_username
-                .expect("instance to have a username"),
+        info!("rotating password of redis user");
+        let password = set_redis_user(&mut connection, &username).await?;
+
+        Ok(self.shared_redis_response(username, password, false))
+    }
+
+    /// Remove the ACL user of a shared Redis cache, if it exists. Its keys are left for Redis to
+    /// evict, as nobody can reach them anymore
+    pub async fn delete_shared_redis(&self, project_name: &str) -> Result<(), Error> {
+        validate_project_name(project_name)?;
+
+        let mut connection = self.redis_connection().await?;
+        let username = self.shared_redis_user(project_name);
+
+        info!("deleting redis user");
+        redis::cmd("ACL")
+            .arg("DELUSER")
+            .arg(&username)
+            .query_async::<_, ()>(&mut connection)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Look up the ACL user of a shared Redis cache without changing anything
+    pub async fn describe_shared_redis(
+        &self,
+        project_name: &str,
+    ) -> Result<Option<DatabaseInfo>, Error> {
+        validate_project_name(project_name)?;
+
+        let mut connection = self.redis_connection().await?;
+        let username = self.shared_redis_user(project_name);
+
+        if !redis_user_exists(&mut connection, &username).await? {
+            return Ok(None);
+        }
+
+        Ok(Some(DatabaseInfo {
+            kind: "shared".to_string(),
+            engine: "redis".to_string(),
+            name: username,
+            status: "available".to_string(),
+            address: self.config.shared_redis_internal_address.clone(),
+            port: REDIS_PORT.to_string(),
+            ..Default::default()
+        }))
+    }
+
+    async fn redis_connection(&self) -> Result<redis::aio::Connection, Error> {
+        let client = self
+            .redis_client
+            .as_ref()
+            .ok_or(Error::RedisNotConfigured)?;
+
+        Ok(client.get_async_connection().await?)
+    }
+
+    /// Name of the ACL user of a project on the shared Redis. It shares the role prefix, so
+    /// environments sharing a Redis server keep their users and keys apart
+    fn shared_redis_user(&self, project_name: &str) -> String {
+        format!("{}{project_name}", self.config.role_prefix)
+    }
+
+    fn shared_redis_response(
+        &self,
+        username: String,
+        password: String,
+        created: bool,
+    ) -> DatabaseResponse {
+        DatabaseResponse {
+            engine: "redis".to_string(),
+            key_prefix: redis_key_prefix(&username),
+            username,
             password,
-            database_name: instance
-                .db_name
-                .expect("instance to have a default database"),
-            address_private: address.clone(),
-            address_public: address,
-            port: engine_to_port(engine),
-        })
+            database_name: "0".to_string(),
+            address_private: self.config.shared_redis_internal_address.clone(),
+            address_public: self
+                .config
+                .shared_redis_public_address
+                .clone()
+                .unwrap_or_default(),
+            port: REDIS_PORT.to_string(),
+            created,
+            ..Default::default()
+        }
     }
-}
 
-#[tonic::async_trait]
-impl Provisioner for MyProvisioner {
-    #[tracing::instrument(skip(self))]
-    async fn provision_database(
+    /// Engines requests can be made for, and how they can be provisioned
+    pub fn engines(&self) -> Vec<EngineInfo> {
+        let engine = |engine: &str, default_port: u16, shared: bool, dedicated: bool| EngineInfo {
+            engine: engine.to_string(),
+            default_port: default_port.to_string(),
+            shared,
+            dedicated,
+        };
+
+        vec![
+            engine("postgres", POSTGRES_PORT, true, true),
+            engine("mysql", MYSQL_PORT, false, true),
+            engine("mariadb", MYSQL_PORT, false, true),
+            engine("redis", REDIS_PORT, self.redis_client.is_some(), false),
+        ]
+    }
+
+    /// Provision the database of a single request, for both the single and batch RPCs
+    #[tracing::instrument(
+        skip(self, request),
+        fields(
+            project_name = %request.project_name,
+            correlation_id = field::Empty
+        )
+    )]
+    async fn provision(
         &self,
-        request: Request<DatabaseRequest>,
-    ) -> Result<Response<DatabaseResponse>, Status> {
-        let request = request.into_inner();
-        let db_type = request.db_type.unwrap();
+        mut request: DatabaseRequest,
+        deadline: Option<tokio::time::Instant>,
+    ) -> Result<DatabaseResponse, Status> {
+        correlate(&mut request);
 
-        let reply = match db_type {
-            DbType::Shared(_) => self.request_shared_db(&request.project_name).await?,
-            DbType::AwsRds(AwsRds { engine }) => {
-                self.request_aws_rds(&request.project_name, engine.expect("oneof to be set"))
-                    .await?
+        let db_type = request.db_type.clone().ok_or(Error::MissingDatabaseType)?;
+        let start = Instant::now();
+
+        let provision = async {
+            match db_type {
+                DbType::Shared(shared) => {
+                    self.request_shared_db(&request.project_name, &shared).await
+                }
+                DbType::SharedRedis(_) => self.request_shared_redis(&request.project_name).await,
+                DbType::AwsRds(AwsRds { engine }) => {
+                    let engine = rds_engine(engine)?;
+
+                    if engine.config().aurora {
+                        self.request_aws_aurora(&request.project_name, engine).await
+                    } else {
+                        self.request_aws_rds(&request.project_name, engine).await
+                    }
+                }
             }
         };
 
-        Ok(Response::new(reply))
+        // Stop waiting on AWS once the client has given up on the response
+        let reply = match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline, provision)
+                .await
+                .unwrap_or(Err(Error::DeadlineExceeded)),
+            None => provision.await,
+        };
+
+        let summary = ProvisionSummary::new(&request, reply.as_ref().ok(), start.elapsed());
+        info!(%summary, "provision finished");
+        if let Some(webhook) = &self.webhook {
+            webhook.notify(&summary);
+        }
+
+        let mut reply = reply?;
+        reply.correlation_id = request.correlation_id;
+
+        Ok(reply)
     }
-}
 
-fn generate_password() -> String {
-    rand::thread_rng()
-        .sample_iter(&rand::distributions::Alphanumeric)
-        .take(12)
-        .map(char::from)
-        .collect()
-}
+    /// Version of the shared Postgres, like '14.6'
+    async fn shared_server_version(&self) -> Result<String, Error> {
+        Ok(sqlx::query_scalar("SHOW server_version")
+            .fetch_one(&self.pool)
+            .await?)
+    }
 
-async fn wait_for_instance(
-    client: &Client,
-    name: &str,
-    wait_for: &str,
-) -> Result<DbInstance, Error> {
-    debug!("waiting for {name} to enter {wait_for} state");
-    loop {
-        let instance = client
-            .describe_db_instances()
-            .db_instance_identifier(name)
-            .send()
-            .await?
-            .db_instances
-            .expect("aws to return instances")
-            .get(0)
-            .expect("to find the instance just created or modified")
-            .clone();
+    /// Name of the role of a project on the shared Postgres
+    fn shared_role_name(&self, project_name: &str) -> Result<String, Error> {
+        let name = format!("{}{project_name}", self.config.role_prefix);
+        check_identifier_len(&name)?;
 
-        let status = instance
-            .db_instance_status
-            .as_ref()
-            .expect("instance to have a status")
-            .clone();
+        Ok(name)
+    }
+
+    /// Make sure the database exists, returning whether it had to be created. An existing
+    /// database has to belong to `username` already
+    async fn shared_db(
+        &self,
+        project_name: &str,
+        database_name: &str,
+        username: &str,
+        locale: &DbLocale,
+    ) -> Result<DbOutcome, Error> {
+        self.check_db_owner(database_name, username).await?;
+
+        if !self.config.least_privilege {
+            return shared::shared_db(
+                &self.pool,
+                project_name,
+                database_name,
+                Some(username),
+                locale,
+            )
+            .await;
+        }
 
-        if status == wait_for {
-            return Ok(instance);
+        let outcome =
+            shared::shared_db(&self.pool, project_name, database_name, None, locale).await?;
+        if outcome == DbOutcome::Created {
+            shared::grant_shared_db(
+                &self.pool,
+                &self.pg_connect_options,
+                project_name,
+                database_name,
+                username,
+            )
+            .await?;
         }
 
-        sleep(Duration::from_secs(1)).await;
+        Ok(outcome)
     }
-}
 
-fn engine_to_port(engine: aws_rds::Engine) -> String {
-    match engine {
-        aws_rds::Engine::Postgres(_) => "5432".to_string(),
-        aws_rds::Engine::Mariadb(_) => "3306".to_string(),
-        aws_rds::Engine::Mysql(_) => "3306".to_string(),
+    /// Make sure the database `database_name`, if it exists, belongs to `username`. Requests pick
+    /// custom names themselves, so they could otherwise get at the database of another project
+    async fn check_db_owner(&self, database_name: &str, username: &str) -> Result<(), Error> {
+        match shared::db_belongs_to(&self.pool, database_name, username).await? {
+            Some(false) => Err(Error::ForeignDatabase(database_name.to_string())),
+            Some(true) | None => Ok(()),
+        }
+    }
+
+    /// Count what a shared provision did, for [MyProvisioner::shared_counts]
+    fn count_shared(&self, role: RoleOutcome, database: DbOutcome) {
+        let mut counts = self
+            .shared_counts
+            .lock()
+            .expect("shared counts to not be poisoned");
+
+        match role {
+            RoleOutcome::Created => counts.roles_created += 1,
+            RoleOutcome::PasswordCycled => counts.passwords_cycled += 1,
+        }
+        match database {
+            DbOutcome::Created => counts.databases_created += 1,
+            DbOutcome::Existing => counts.databases_existing += 1,
+        }
+    }
+
+    /// How often shared provisions created or reused roles and databases since this provisioner
+    /// was made, for dashboards to tell new projects from redeploys
+    pub fn shared_counts(&self) -> SharedCounts {
+        *self
+            .shared_counts
+            .lock()
+            .expect("shared counts to not be poisoned")
+    }
+
+    /// Remove the database and role of a shared DB, if they exist
+    pub async fn delete_shared_db(
+        &self,
+        project_name: &str,
+        options: &Shared,
+    ) -> Result<(), Error> {
+        validate_project_name(project_name)?;
+        let database_name = shared_db_name(&self.config.db_prefix, project_name, options)?;
+
+        let username = self.shared_role_name(project_name)?;
+
+        // Default names are reserved to their project, and its role may be gone already
+        if !options.database_name.is_empty() {
+            self.check_db_owner(&database_name, &username).await?;
+        }
+
+        shared::drop_shared_db(&self.pool, &database_name).await?;
+
+        info!("deleting user");
+
+        // The reader and extra roles only have privileges inside the dropped database, so
+        // nothing holds them back. They all start with the name of the project's role
+        let extra_roles: Vec<String> =
+            sqlx::query_scalar("SELECT rolname FROM pg_roles WHERE starts_with(rolname, $1)")
+                .bind(format!("{username}_"))
+                .fetch_all(&self.pool)
+                .await?;
+        for role in extra_roles {
+            shared::drop_shared_role(&self.pool, &role).await?;
+        }
+        shared::drop_shared_role(&self.pool, &username).await
+    }
+
+    /// List all the resources created for a project
+    pub async fn project_databases(&self, project_name: &str) -> Result<Vec<DatabaseInfo>, Error> {
+        validate_project_name(project_name)?;
+
+        let mut databases: Vec<_> = self
+            .describe_shared_db(project_name, &Shared::default())
+            .await?
+            .into_iter()
+            .collect();
+        databases.extend(self.list_rds_instances(project_name).await?);
+
+        Ok(databases)
+    }
+
+    /// Look up the role and database of a shared DB without changing anything
+    pub async fn describe_shared_db(
+        &self,
+        project_name: &str,
+        options: &Shared,
+    ) -> Result<Option<DatabaseInfo>, Error> {
+        validate_project_name(project_name)?;
+        let database_name = shared_db_name(&self.config.db_prefix, project_name, options)?;
+
+        let username = self.shared_role_name(project_name)?;
+
+        let matching_user = sqlx::query("SELECT rolname FROM pg_roles WHERE rolname = $1")
+            .bind(&username)
+            .fetch_optional(&self.pool)
+            .await?;
+        let matching_db = sqlx::query("SELECT datname FROM pg_database WHERE datname = $1")
+            .bind(&database_name)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        let (name, status) = match (matching_db.is_some(), matching_user.is_some()) {
+            (true, true) => (database_name, "available"),
+            (true, false) => (database_name, "missing-role"),
+            (false, true) => (username, "missing-database"),
+            (false, false) => return Ok(None),
+        };
+
+        Ok(Some(DatabaseInfo {
+            kind: "shared".to_string(),
+            engine: "postgres".to_string(),
+            name,
+            status: status.to_string(),
+            address: self.fqdn.clone(),
+            port: POSTGRES_PORT.to_string(),
+            ..Default::default()
+        }))
+    }
+
+    /// Give the master user of the RDS instance of a project a new password, without changing
+    /// anything else about the instance
+    pub async fn rotate_aws_rds(
+        &self,
+        project_name: &str,
+        engine: &aws_rds::Engine,
+    ) -> Result<DatabaseResponse, Error> {
+        validate_project_name(project_name)?;
+        if engine.config().aurora {
+            return self.rotate_aws_aurora(project_name, engine).await;
+        }
+
+        let client = self.rds_client.as_ref();
+        let instance_name = instance_name(project_name, engine);
+        let _lock = self.lock_instance(&instance_name).await;
+
+        if self.find_instance(&instance_name).await?.is_none() {
+            return Err(Error::DatabaseNotFound(instance_name));
+        }
+
+        info!("rotating master password of AWS RDS {instance_name}");
+        let password = generate_password();
+        let reset = ModifyInstance {
+            identifier: instance_name.clone(),
+            master_password: Some(password.clone()),
+            apply_immediately: Some(true),
+            ..Default::default()
+        };
+        RETRY_BACKOFF
+            .retry(RETRY_ATTEMPTS, RdsError::is_retryable, || {
+                client.modify_instance(reset.clone())
+            })
+            .await?;
+
+        wait_for_modification(&self.config, &instance_name, || {
+            instance_status(client, &instance_name)
+        })
+        .await?;
+        let instance = wait_for_instance(
+            client,
+            &self.config,
+            &instance_name,
+            InstanceStatus::Available,
+        )
+        .await?;
+
+        let read_replica_address = self
+            .find_instance(&read_replica_name(&instance_name))
+            .await?
+            .and_then(|replica| replica.endpoint)
+            .and_then(|endpoint| endpoint.address)
+            .unwrap_or_default();
+
+        Ok(DatabaseResponse {
+            read_replica_address,
+            ..self
+                .aws_rds_response(
+                    project_name,
+                    engine,
+                    instance,
+                    MasterPassword::Reset(password),
+                )
+                .await?
+        })
+    }
+
+    /// Connection details of the RDS instance of a project, without resetting its password. Only
+    /// works when clients do not need the password from the response, because they use IAM auth
+    /// or the password is kept in a secret
+    pub async fn aws_rds_connection_info(
+        &self,
+        project_name: &str,
+        engine: &aws_rds::Engine,
+    ) -> Result<DatabaseResponse, Error> {
+        validate_project_name(project_name)?;
+        if engine.config().aurora {
+            return self.aurora_connection_info(project_name, engine).await;
+        }
+
+        let instance_name = instance_name(project_name, engine);
+        let instance = match self.find_instance(&instance_name).await? {
+            Some(instance) => instance,
+            None => return Err(Error::DatabaseNotFound(instance_name)),
+        };
+        let password = match self
+            .stored_password(&instance_name, instance.iam_database_authentication_enabled)
+            .await?
+        {
+            Some(password) => password,
+            None => return Err(Error::CredentialsNotStored(instance_name)),
+        };
+
+        let read_replica_address = self
+            .find_instance(&read_replica_name(&instance_name))
+            .await?
+            .and_then(|replica| replica.endpoint)
+            .and_then(|endpoint| endpoint.address)
+            .unwrap_or_default();
+
+        Ok(DatabaseResponse {
+            read_replica_address,
+            ..self
+                .aws_rds_response(project_name, engine, instance, password)
+                .await?
+        })
+    }
+
+    /// Look up the RDS instance of a project without changing anything
+    pub async fn describe_aws_rds(
+        &self,
+        project_name: &str,
+        engine: &aws_rds::Engine,
+    ) -> Result<Option<DatabaseInfo>, Error> {
+        validate_project_name(project_name)?;
+        if engine.config().aurora {
+            return self.describe_aws_aurora(project_name, engine).await;
+        }
+
+        let instance_name = instance_name(project_name, engine);
+        let instance = match self.find_instance(&instance_name).await? {
+            Some(instance) => instance,
+            None => return Ok(None),
+        };
+
+        let mut info = instance_info(&instance);
+        let mut creation_starts = self
+            .creation_starts
+            .lock()
+            .expect("creation starts to not be poisoned");
+        if info.create_time == 0 {
+            info.create_time = creation_starts
+                .get(&instance_name)
+                .map(|start| unix_secs(*start))
+                .unwrap_or_default();
+        } else {
+            creation_starts.remove(&instance_name);
+        }
+
+        Ok(Some(info))
+    }
+
+    /// Remember that creating `instance_name` starts now
+    fn record_creation_start(&self, instance_name: &str) {
+        self.creation_starts
+            .lock()
+            .expect("creation starts to not be poisoned")
+            .insert(instance_name.to_string(), SystemTime::now());
+    }
+
+    /// Delete the RDS instance of a project, if it exists. A final snapshot is taken unless the
+    /// request skips it, and its identifier is returned
+    pub async fn delete_aws_rds(
+        &self,
+        project_name: &str,
+        engine: &aws_rds::Engine,
+    ) -> Result<Option<String>, Error> {
+        validate_project_name(project_name)?;
+        if engine.config().aurora {
+            return self.delete_aws_aurora(project_name, engine).await;
+        }
+
+        let client = self.rds_client.as_ref();
+        let instance_name = instance_name(project_name, engine);
+        let _lock = self.lock_instance(&instance_name).await;
+
+        let instance = match self.find_instance(&instance_name).await? {
+            Some(instance) => instance,
+            None => return Ok(None),
+        };
+
+        if check_deletion_protection(&instance, self.config.disable_deletion_protection)? {
+            info!("disabling deletion protection on {instance_name}");
+
+            let modify = ModifyInstance {
+                identifier: instance_name.clone(),
+                deletion_protection: Some(false),
+                apply_immediately: Some(true),
+                ..Default::default()
+            };
+            RETRY_BACKOFF
+                .retry(RETRY_ATTEMPTS, RdsError::is_retryable, || {
+                    client.modify_instance(modify.clone())
+                })
+                .await?;
+
+            // A delete is refused until the instance is done being modified
+            wait_for_unprotected(client, &self.config, &instance_name).await?;
+        }
+
+        // Replicas would be left behind as standalone instances otherwise
+        let replica_name = read_replica_name(&instance_name);
+        if self.find_instance(&replica_name).await?.is_some() {
+            info!("deleting read replica {replica_name}");
+
+            let deleted = client
+                .delete_instance(DeleteInstance {
+                    identifier: replica_name.clone(),
+                    final_snapshot: None,
+                })
+                .await;
+
+            match deleted {
+                Ok(()) | Err(RdsError::InstanceNotFound) => {}
+                Err(error) => return Err(error.into()),
+            }
+
+            // A source instance cannot be deleted while it still has replicas
+            wait_for_deletion(client, &self.config, &replica_name).await?;
+        }
+
+        let final_snapshot = if engine.config().skip_final_snapshot {
+            warn!("deleting AWS RDS {instance_name} without a final snapshot");
+            None
+        } else {
+            Some(final_snapshot_name(&instance_name))
+        };
+
+        info!(?final_snapshot, "deleting AWS RDS {instance_name}");
+        let deleted = client
+            .delete_instance(DeleteInstance {
+                identifier: instance_name,
+                final_snapshot: final_snapshot.clone(),
+            })
+            .await;
+
+        match deleted {
+            Ok(()) => Ok(final_snapshot),
+            Err(RdsError::InstanceNotFound) => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Wait for other requests working on `instance_name` to finish. Requests for the same
+    /// instance would otherwise race to create it, while different instances can be worked on
+    /// at the same time
+    async fn lock_instance(&self, instance_name: &str) -> InstanceLock {
+        InstanceLock::acquire(&self.instance_locks, instance_name).await
+    }
+
+    /// Storage to grow `instance` to when it has run out of space. Full instances are rejected
+    /// when no increase is configured
+    fn storage_increase(&self, instance: &DbInstance) -> Result<Option<i32>, Error> {
+        let status: InstanceStatus = instance
+            .db_instance_status
+            .as_deref()
+            .unwrap_or_default()
+            .into();
+        if status != InstanceStatus::StorageFull {
+            return Ok(None);
+        }
+
+        let instance_name = instance.db_instance_identifier.clone().unwrap_or_default();
+        let allocated_storage = instance.allocated_storage;
+
+        match self.config.storage_full_increase {
+            Some(increase) => {
+                // Round up so small instances still grow by at least the configured percentage
+                let increased =
+                    allocated_storage + (allocated_storage * increase as i32 + 99) / 100;
+                warn!(
+                    allocated_storage,
+                    increased, "{instance_name} is out of storage, increasing it"
+                );
+
+                Ok(Some(increased))
+            }
+            None => Err(Error::StorageFull {
+                instance: instance_name,
+                allocated_storage,
+            }),
+        }
+    }
+
+    /// Make sure `snapshot` exists and can be restored as `engine`
+    async fn check_snapshot(&self, snapshot: &str, engine: &aws_rds::Engine) -> Result<(), Error> {
+        let found = self
+            .rds_client
+            .describe_snapshot(snapshot)
+            .await?
+            .ok_or_else(|| Error::SnapshotNotFound(snapshot.to_string()))?;
+
+        let snapshot_engine = found.engine.unwrap_or_default();
+        if snapshot_engine != engine_spec(engine).engine_str {
+            return Err(Error::SnapshotEngineMismatch {
+                snapshot: snapshot.to_string(),
+                engine: snapshot_engine,
+                requested: engine.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Delete an instance which will not recover by itself, so it can be created again. Its data
+    /// cannot be saved, so no final snapshot is taken
+    async fn delete_stuck_instance(&self, instance: &DbInstance) -> Result<(), Error> {
+        check_deletion_protection(instance, false)?;
+
+        let instance_name = instance
+            .db_instance_identifier
+            .clone()
+            .expect("instance to have an identifier");
+        warn!(
+            status = instance.db_instance_status.as_deref(),
+            "deleting stuck AWS RDS {instance_name} to recreate it"
+        );
+
+        let deleted = self
+            .rds_client
+            .delete_instance(DeleteInstance {
+                identifier: instance_name.clone(),
+                final_snapshot: None,
+            })
+            .await;
+
+        match deleted {
+            Ok(()) | Err(RdsError::InstanceNotFound) => {}
+            Err(error) => return Err(error.into()),
+        }
+
+        wait_for_deletion(self.rds_client.as_ref(), &self.config, &instance_name).await
+    }
+
+    async fn find_instance(&self, instance_name: &str) -> Result<Option<DbInstance>, Error> {
+        match self.rds_client.describe_instance(instance_name).await {
+            Ok(instance) => Ok(Some(instance)),
+            Err(RdsError::InstanceNotFound) => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    async fn find_cluster(&self, cluster_name: &str) -> Result<Option<DbCluster>, Error> {
+        match self.rds_client.describe_cluster(cluster_name).await {
+            Ok(cluster) => Ok(Some(cluster)),
+            Err(RdsError::ClusterNotFound) => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Make sure the project has room for `database_name` if it is a new shared DB
+    async fn check_shared_db_quota(
+        &self,
+        project_name: &str,
+        database_name: &str,
+    ) -> Result<(), Error> {
+        let limit = match self.config.shared_db_quota {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+
+        // Owners have every privilege, so this also covers databases the role was only granted
+        let owned: Vec<String> = sqlx::query_scalar(
+            "SELECT datname FROM pg_database d JOIN pg_roles r ON has_database_privilege(r.oid, d.oid, 'CREATE') WHERE r.rolname = $1",
+        )
+        .bind(self.shared_role_name(project_name)?)
+        .fetch_all(&self.pool)
+        .await?;
+
+        check_quota(
+            project_name,
+            "shared database",
+            &owned,
+            database_name,
+            limit,
+        )
+    }
+
+    /// Make sure the project has room for `instance_name` if it is a new RDS instance or Aurora
+    /// cluster. Read replicas come with the instance they copy, and the instances of a cluster
+    /// with that cluster, so neither counts on its own
+    async fn check_rds_quota(&self, project_name: &str, instance_name: &str) -> Result<(), Error> {
+        let limit = match self.config.rds_quota {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+
+        let owned: Vec<String> = self
+            .tagged_instances(project_name)
+            .await?
+            .into_iter()
+            .filter(|instance| {
+                instance
+                    .read_replica_source_db_instance_identifier
+                    .is_none()
+            })
+            .filter_map(|instance| {
+                instance
+                    .db_cluster_identifier
+                    .or(instance.db_instance_identifier)
+            })
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+
+        check_quota(project_name, "RDS instance", &owned, instance_name, limit)
+    }
+
+    async fn list_rds_instances(&self, project_name: &str) -> Result<Vec<DatabaseInfo>, Error> {
+        Ok(self
+            .tagged_instances(project_name)
+            .await?
+            .iter()
+            .map(instance_info)
+            .collect())
+    }
+
+    /// Every RDS instance created for `project_name`
+    async fn tagged_instances(&self, project_name: &str) -> Result<Vec<DbInstance>, Error> {
+        let mut instances = Vec::new();
+        let mut marker = None;
+
+        loop {
+            let (page, next_marker) = self.rds_client.describe_instances(marker).await?;
+
+            instances.extend(page);
+            marker = next_marker;
+
+            if marker.is_none() {
+                break;
+            }
+        }
+
+        Ok(instances
+            .into_iter()
+            .filter(|instance| is_tagged_for(instance, project_name))
+            .collect())
+    }
+
+    #[tracing::instrument(
+        skip(self, engine),
+        fields(
+            %project_name,
+            %engine,
+            instance_name = %instance_name(project_name, &engine)
+        )
+    )]
+    async fn request_aws_rds(
+        &self,
+        project_name: &str,
+        engine: aws_rds::Engine,
+    ) -> Result<DatabaseResponse, Error> {
+        validate_project_name(project_name)?;
+
+        let client = self.rds_client.as_ref();
+        let instance_class = self.instance_class(&engine)?;
+
+        let availability_zone = engine.config().availability_zone.clone();
+        if let (Some(zone), Some(true)) = (&availability_zone, engine.config().multi_az) {
+            return Err(Error::AvailabilityZoneWithMultiAz(zone.clone()));
+        }
+
+        let network = self.network(&engine)?;
+        let multi_az = match engine.config().multi_az {
+            Some(requested) => Some(self.multi_az(requested, &network).await?),
+            None => None,
+        };
+
+        let security_group_ids = self.security_group_ids(&engine, &network);
+        let publicly_accessible = engine.config().publicly_accessible;
+        let enable_iam_auth = engine.config().enable_iam_auth;
+        let parameter_group = parameter_group(engine.config())?;
+        let cloudwatch_logs_exports = cloudwatch_logs_exports(&engine)?;
+        let (storage_type, iops) = storage(engine.config())?;
+        let (maintenance_window, backup_window) = preferred_windows(engine.config())?;
+        let spec = engine_spec(&engine);
+
+        let password = generate_password();
+        let instance_name = instance_name(project_name, &engine);
+        let _lock = self.lock_instance(&instance_name).await;
+        self.check_rds_quota(project_name, &instance_name).await?;
+
+        let existing = match self.find_instance(&instance_name).await? {
+            Some(instance) if engine.config().force_recreate && is_stuck(&instance) => {
+                self.delete_stuck_instance(&instance).await?;
+
+                None
+            }
+            existing => existing,
+        };
+        // Refuse before the password is reset, or the client would be left without the new one
+        if let Some(existing) = &existing {
+            if engine.config().read_replica && existing.backup_retention_period == 0 {
+                return Err(Error::ReadReplicaNeedsBackups(instance_name));
+            }
+        }
+        let resize = match &existing {
+            Some(existing) => check_resize(existing, engine.config(), &instance_class)?,
+            None => None,
+        };
+
+        let storage_increase = match &existing {
+            Some(existing) => self.storage_increase(existing)?,
+            None => None,
+        };
+
+        // Class the instance will have once this request is done
+        let final_class = match (&resize, &existing) {
+            (Some(resize), _) => resize.clone(),
+            (None, Some(existing)) => existing.db_instance_class.clone().unwrap_or_default(),
+            (None, None) => instance_class.to_string(),
+        };
+        check_capabilities(engine.config(), &final_class)?;
+        check_performance_insights(engine.config())?;
+        let performance_insights = engine.config().enable_performance_insights;
+        let performance_insights_retention = engine.config().performance_insights_retention_period;
+
+        // The new password has to work as soon as it is handed out, and a full instance is
+        // read-only, so its storage cannot wait for the maintenance window either
+        let apply_immediately =
+            storage_increase.is_some() || engine.config().apply_immediately.unwrap_or(true);
+        debug!("trying to get AWS RDS instance: {instance_name}");
+        let modify = ModifyInstance {
+            identifier: instance_name.clone(),
+            instance_class: resize,
+            allocated_storage: storage_increase,
+            master_password: Some(password.clone()),
+            multi_az,
+            deletion_protection: engine.config().deletion_protection,
+            security_group_ids: security_group_ids.clone(),
+            publicly_accessible,
+            iam_auth: enable_iam_auth,
+            performance_insights,
+            performance_insights_retention,
+            parameter_group: parameter_group.clone(),
+            ca_certificate: None,
+            preferred_maintenance_window: maintenance_window.clone(),
+            preferred_backup_window: backup_window.clone(),
+            apply_immediately: Some(apply_immediately),
+        };
+
+        if let Some(existing) = existing {
+            let reuse = engine.config().reuse_password;
+            if let Some(password) = self.kept_password(&existing, &modify, reuse).await? {
+                debug!("AWS RDS {instance_name} is up and unchanged, leaving it as it is");
+
+                return self
+                    .existing_aws_rds_response(project_name, &engine, existing, password)
+                    .await;
+            }
+        }
+
+        let instance = RETRY_BACKOFF
+            .retry(RETRY_ATTEMPTS, RdsError::is_retryable, || {
+                client.modify_instance(modify.clone())
+            })
+            .await;
+
+        let created = match instance {
+            Ok(_) => {
+                // Let the credential reset or other changes start before waiting for the instance
+                wait_for_modification(&self.config, &instance_name, || {
+                    instance_status(client, &instance_name)
+                })
+                .await?;
+
+                false
+            }
+            Err(RdsError::InstanceNotFound) => {
+                let tags = vec![Tag::builder().key(PROJECT_TAG).value(project_name).build()];
+
+                if let Some(snapshot) = &engine.config().restore_from_snapshot {
+                    debug!("restoring AWS RDS {instance_name} from snapshot {snapshot}");
+                    self.check_snapshot(snapshot, &engine).await?;
+
+                    let restore = RestoreInstance {
+                        identifier: instance_name.clone(),
+                        snapshot: snapshot.clone(),
+                        instance_class: instance_class.to_string(),
+                        publicly_accessible: publicly_accessible.unwrap_or(true),
+                        multi_az: multi_az.unwrap_or_default(),
+                        availability_zone,
+                        deletion_protection: engine
+                            .config()
+                            .deletion_protection
+                            .unwrap_or_default(),
+                        iam_auth: enable_iam_auth.unwrap_or_default(),
+                        parameter_group,
+                        subnet_group: network.subnet_group.clone(),
+                        security_group_ids,
+                        cloudwatch_logs_exports,
+                        tags,
+                    };
+                    let reset = ModifyInstance {
+                        identifier: instance_name.clone(),
+                        master_password: Some(password.clone()),
+                        performance_insights,
+                        performance_insights_retention,
+                        ca_certificate: self.config.ca_certificate.clone(),
+                        preferred_maintenance_window: maintenance_window,
+                        preferred_backup_window: backup_window,
+                        apply_immediately: Some(true),
+                        ..Default::default()
+                    };
+
+                    self.record_creation_start(&instance_name);
+                    restore_instance(client, &self.config, restore, reset).await?;
+
+                    true
+                } else {
+                    debug!("creating new AWS RDS {instance_name}");
+
+                    // Existing instances keep whatever username they were created with
+                    let master_username =
+                        aws_rds_master_username(project_name, &common_engine(&engine))?;
+
+                    let create = CreateInstance {
+                        identifier: instance_name.clone(),
+                        master_username,
+                        master_password: password.clone(),
+                        engine: spec.engine_str.to_string(),
+                        instance_class: instance_class.to_string(),
+                        allocated_storage: self.config.allocated_storage,
+                        storage_type,
+                        iops,
+                        // Backups are only kept for read replicas, which need them
+                        backup_retention_period: if engine.config().read_replica { 1 } else { 0 },
+                        publicly_accessible: publicly_accessible.unwrap_or(true),
+                        multi_az: multi_az.unwrap_or_default(),
+                        availability_zone,
+                        deletion_protection: engine
+                            .config()
+                            .deletion_protection
+                            .unwrap_or_default(),
+                        iam_auth: enable_iam_auth.unwrap_or_default(),
+                        performance_insights: performance_insights.unwrap_or_default(),
+                        performance_insights_retention,
+                        db_name: spec.default_db_name.to_string(),
+                        parameter_group,
+                        subnet_group: network.subnet_group.clone(),
+                        security_group_ids,
+                        ca_certificate: self.config.ca_certificate.clone(),
+                        cloudwatch_logs_exports,
+                        preferred_maintenance_window: maintenance_window,
+                        preferred_backup_window: backup_window,
+                        tags,
+                    };
+                    self.record_creation_start(&instance_name);
+                    let output = RETRY_BACKOFF
+                        .retry(RETRY_ATTEMPTS, RdsError::is_retryable, || {
+                            client.create_instance(create.clone())
+                        })
+                        .await;
+
+                    match output {
+                        Ok(_) => {
+                            wait_for_instance(
+                                client,
+                                &self.config,
+                                &instance_name,
+                                InstanceStatus::Creating,
+                            )
+                            .await
+                            .map_err(creation_failed)?;
+
+                            true
+                        }
+                        // Another request created the instance since it was not found, so treat
+                        // it as an existing one. It can only be modified once it is up
+                        Err(RdsError::InstanceAlreadyExists) => {
+                            info!("AWS RDS {instance_name} was created by another request");
+
+                            wait_for_instance(
+                                client,
+                                &self.config,
+                                &instance_name,
+                                InstanceStatus::Available,
+                            )
+                            .await?;
+                            RETRY_BACKOFF
+                                .retry(RETRY_ATTEMPTS, RdsError::is_retryable, || {
+                                    client.modify_instance(modify.clone())
+                                })
+                                .await?;
+                            wait_for_modification(&self.config, &instance_name, || {
+                                instance_status(client, &instance_name)
+                            })
+                            .await?;
+
+                            false
+                        }
+                        Err(error) => return Err(error.into()),
+                    }
+                }
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        // Wait for up
+        let instance = wait_for_instance(
+            client,
+            &self.config,
+            &instance_name,
+            InstanceStatus::Available,
+        )
+        .await
+        .map_err(|error| {
+            if created {
+                creation_failed(error)
+            } else {
+                error
+            }
+        })?;
+
+        Ok(DatabaseResponse {
+            created,
+            ..self
+                .existing_aws_rds_response(
+                    project_name,
+                    &engine,
+                    instance,
+                    MasterPassword::Reset(password),
+                )
+                .await?
+        })
+    }
+
+    /// Response for an instance which is up, making sure it has the read replica it was asked for
+    async fn existing_aws_rds_response(
+        &self,
+        project_name: &str,
+        engine: &aws_rds::Engine,
+        instance: DbInstance,
+        password: MasterPassword,
+    ) -> Result<DatabaseResponse, Error> {
+        let read_replica_address = if engine.config().read_replica {
+            self.read_replica(&instance, project_name).await?
+        } else {
+            String::new()
+        };
+
+        Ok(DatabaseResponse {
+            read_replica_address,
+            ..self
+                .aws_rds_response(project_name, engine, instance, password)
+                .await?
+        })
+    }
+
+    /// The password of an instance does not have to be reset when clients do not get it in the
+    /// response anyway, because they use IAM auth or because it is kept in a secret. Returns what
+    /// to hand out instead when the instance is also up and `modify` has nothing else to change,
+    /// or the client asked to `reuse` the password whatever else it asked for
+    async fn kept_password(
+        &self,
+        instance: &DbInstance,
+        modify: &ModifyInstance,
+        reuse: bool,
+    ) -> Result<Option<MasterPassword>, Error> {
+        let status: InstanceStatus = instance
+            .db_instance_status
+            .as_deref()
+            .unwrap_or_default()
+            .into();
+        if !status.reached(&InstanceStatus::Available)
+            || !(reuse || changes_nothing(modify, instance))
+        {
+            return Ok(None);
+        }
+
+        self.stored_password(
+            instance
+                .db_instance_identifier
+                .as_deref()
+                .expect("instance to have an identifier"),
+            instance.iam_database_authentication_enabled,
+        )
+        .await
+    }
+
+    /// What to hand out instead of the master password of instance or cluster `identifier` when
+    /// clients can get it without it being reset. That is nothing with IAM auth, or the secret it
+    /// is kept in
+    async fn stored_password(
+        &self,
+        identifier: &str,
+        iam_auth: bool,
+    ) -> Result<Option<MasterPassword>, Error> {
+        if iam_auth {
+            return Ok(Some(MasterPassword::Kept {
+                secret_arn: String::new(),
+            }));
+        }
+
+        if !self.config.password_secrets {
+            return Ok(None);
+        }
+
+        let name = password_secret_name(identifier);
+        let secret_arn = RETRY_BACKOFF
+            .retry(RETRY_ATTEMPTS, SecretsError::is_retryable, || {
+                self.secrets_client.secret_arn(&name)
+            })
+            .await?;
+
+        Ok(secret_arn.map(|secret_arn| MasterPassword::Kept { secret_arn }))
+    }
+
+    /// Connection details of an instance which is up, which the master user can log in to with
+    /// `password`
+    async fn aws_rds_response(
+        &self,
+        project_name: &str,
+        engine: &aws_rds::Engine,
+        instance: DbInstance,
+        password: MasterPassword,
+    ) -> Result<DatabaseResponse, Error> {
+        let instance_name = instance
+            .db_instance_identifier
+            .clone()
+            .expect("instance to have an identifier");
+        let ca_cert_identifier = instance
+            .ca_certificate_identifier
+            .clone()
+            .unwrap_or_default();
+
+        // TODO: find private IP somehow
+        let endpoint = instance.endpoint.expect("instance to have an endpoint");
+        let address = endpoint.address.expect("endpoint to have an address");
+
+        // Instances can listen on another port than the default of their engine
+        let port = match endpoint.port {
+            0 => engine_spec(engine).port.to_string(),
+            port => port.to_string(),
+        };
+
+        // Private-only instances cannot be reached from outside their VPC
+        let address_public = if instance.publicly_accessible {
+            self.config.public_address(&address)
+        } else {
+            String::new()
+        };
+
+        // Clients generate a token for IAM auth, so don't hand out a password they shouldn't use
+        let iam_auth = instance.iam_database_authentication_enabled;
+
+        let (password, password_secret_arn) = match password {
+            MasterPassword::Reset(password) => {
+                let password = if iam_auth { String::new() } else { password };
+
+                self.store_password(project_name, &instance_name, password, iam_auth)
+                    .await?
+            }
+            MasterPassword::Kept { secret_arn } => (String::new(), secret_arn),
+        };
+
+        Ok(DatabaseResponse {
+            engine: engine.to_string(),
+            username: instance
+                .master_username
+                .expect("instance to have a username"),
+            password,
+            database_name: instance
+                .db_name
+                .expect("instance to have a default database"),
+            address_private: address,
+            address_public,
+            port,
+            iam_auth,
+            ca_cert_identifier,
+            is_publicly_accessible: instance.publicly_accessible,
+            ca_bundle_url: RDS_CA_BUNDLE_URL.to_string(),
+            password_secret_arn,
+            engine_version: instance.engine_version.unwrap_or_default(),
+            ..Default::default()
+        })
+    }
+
+    /// Put the master password of `instance_name` in Secrets Manager if it is configured to keep
+    /// them, returning the password and secret ARN to hand out
+    async fn store_password(
+        &self,
+        project_name: &str,
+        instance_name: &str,
+        password: String,
+        iam_auth: bool,
+    ) -> Result<(String, String), Error> {
+        if !self.config.password_secrets || iam_auth {
+            return Ok((password, String::new()));
+        }
+
+        // Every provision and rotation resets the password, so the secret gets the new one as its
+        // current version and the ARN stays the same
+        let put = PutSecret {
+            name: password_secret_name(instance_name),
+            value: password,
+            project: project_name.to_string(),
+        };
+        let arn = RETRY_BACKOFF
+            .retry(RETRY_ATTEMPTS, SecretsError::is_retryable, || {
+                self.secrets_client.put_secret(put.clone())
+            })
+            .await?;
+
+        Ok((String::new(), arn))
+    }
+
+    /// Make sure a project has an Aurora cluster with a writer instance, handing out the writer
+    /// endpoint of the cluster
+    #[tracing::instrument(
+        skip(self, engine),
+        fields(
+            %project_name,
+            %engine,
+            cluster_name = %instance_name(project_name, &engine)
+        )
+    )]
+    async fn request_aws_aurora(
+        &self,
+        project_name: &str,
+        engine: aws_rds::Engine,
+    ) -> Result<DatabaseResponse, Error> {
+        validate_project_name(project_name)?;
+        let aurora_engine = aurora_engine(&engine)?;
+        let network = self.network(&engine)?;
+
+        let client = self.rds_client.as_ref();
+        let instance_class = if engine.config().instance_class.is_empty() {
+            AURORA_DEFAULT_INSTANCE_CLASS.to_string()
+        } else {
+            self.instance_class(&engine)?.to_string()
+        };
+        let spec = engine_spec(&engine);
+        let publicly_accessible = engine.config().publicly_accessible.unwrap_or(true);
+        let iam_auth = engine.config().enable_iam_auth.unwrap_or_default();
+        let tags = vec![Tag::builder().key(PROJECT_TAG).value(project_name).build()];
+
+        let password = generate_password();
+        let cluster_name = instance_name(project_name, &engine);
+        let writer_name = aurora_writer_name(&cluster_name);
+        let _lock = self.lock_instance(&cluster_name).await;
+        self.check_rds_quota(project_name, &cluster_name).await?;
+
+        debug!("trying to get Aurora cluster: {cluster_name}");
+        let modify = ModifyCluster {
+            identifier: cluster_name.clone(),
+            master_password: Some(password.clone()),
+            apply_immediately: Some(true),
+            ..Default::default()
+        };
+        let cluster = RETRY_BACKOFF
+            .retry(RETRY_ATTEMPTS, RdsError::is_retryable, || {
+                client.modify_cluster(modify.clone())
+            })
+            .await;
+
+        let created = match cluster {
+            Ok(_) => {
+                wait_for_modification(&self.config, &cluster_name, || {
+                    cluster_status(client, &cluster_name)
+                })
+                .await?;
+
+                false
+            }
+            Err(RdsError::ClusterNotFound) => {
+                debug!("creating new Aurora cluster {cluster_name}");
+
+                let create = CreateCluster {
+                    identifier: cluster_name.clone(),
+                    master_username: aws_rds_master_username(
+                        project_name,
+                        &common_engine(&engine),
+                    )?,
+                    master_password: password.clone(),
+                    engine: aurora_engine.to_string(),
+                    database_name: spec.default_db_name.to_string(),
+                    deletion_protection: engine.config().deletion_protection.unwrap_or_default(),
+                    iam_auth,
+                    subnet_group: network.subnet_group.clone(),
+                    security_group_ids: self.security_group_ids(&engine, &network),
+                    tags: tags.clone(),
+                };
+                RETRY_BACKOFF
+                    .retry(RETRY_ATTEMPTS, RdsError::is_retryable, || {
+                        client.create_cluster(create.clone())
+                    })
+                    .await?;
+
+                true
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        let cluster = wait_for_cluster(
+            client,
+            &self.config,
+            &cluster_name,
+            InstanceStatus::Available,
+        )
+        .await
+        .map_err(|error| {
+            if created {
+                creation_failed(error)
+            } else {
+                error
+            }
+        })?;
+
+        // A cluster only has storage, its writer instance is what serves the queries
+        if self.find_instance(&writer_name).await?.is_none() {
+            debug!("creating writer {writer_name} of Aurora cluster {cluster_name}");
+
+            let create = CreateClusterInstance {
+                identifier: writer_name.clone(),
+                cluster: cluster_name.clone(),
+                engine: aurora_engine.to_string(),
+                instance_class,
+                publicly_accessible,
+                tags,
+            };
+            RETRY_BACKOFF
+                .retry(RETRY_ATTEMPTS, RdsError::is_retryable, || {
+                    client.create_cluster_instance(create.clone())
+                })
+                .await?;
+
+            wait_for_instance(client, &self.config, &writer_name, InstanceStatus::Creating)
+                .await
+                .map_err(creation_failed)?;
+        }
+
+        let writer = wait_for_instance(
+            client,
+            &self.config,
+            &writer_name,
+            InstanceStatus::Available,
+        )
+        .await?;
+
+        Ok(DatabaseResponse {
+            created,
+            ..self
+                .aurora_response(
+                    project_name,
+                    &engine,
+                    cluster,
+                    writer,
+                    MasterPassword::Reset(password),
+                )
+                .await?
+        })
+    }
+
+    /// Give the master user of the Aurora cluster of a project a new password
+    async fn rotate_aws_aurora(
+        &self,
+        project_name: &str,
+        engine: &aws_rds::Engine,
+    ) -> Result<DatabaseResponse, Error> {
+        let client = self.rds_client.as_ref();
+        let cluster_name = instance_name(project_name, engine);
+        let _lock = self.lock_instance(&cluster_name).await;
+
+        if self.find_cluster(&cluster_name).await?.is_none() {
+            return Err(Error::DatabaseNotFound(cluster_name));
+        }
+
+        info!("rotating master password of Aurora cluster {cluster_name}");
+        let password = generate_password();
+        let reset = ModifyCluster {
+            identifier: cluster_name.clone(),
+            master_password: Some(password.clone()),
+            apply_immediately: Some(true),
+            ..Default::default()
+        };
+        RETRY_BACKOFF
+            .retry(RETRY_ATTEMPTS, RdsError::is_retryable, || {
+                client.modify_cluster(reset.clone())
+            })
+            .await?;
+
+        wait_for_modification(&self.config, &cluster_name, || {
+            cluster_status(client, &cluster_name)
+        })
+        .await?;
+        let cluster = wait_for_cluster(
+            client,
+            &self.config,
+            &cluster_name,
+            InstanceStatus::Available,
+        )
+        .await?;
+        let writer = wait_for_instance(
+            client,
+            &self.config,
+            &aurora_writer_name(&cluster_name),
+            InstanceStatus::Available,
+        )
+        .await?;
+
+        self.aurora_response(
+            project_name,
+            engine,
+            cluster,
+            writer,
+            MasterPassword::Reset(password),
+        )
+        .await
+    }
+
+    /// Connection details of the Aurora cluster of a project, without resetting its password
+    async fn aurora_connection_info(
+        &self,
+        project_name: &str,
+        engine: &aws_rds::Engine,
+    ) -> Result<DatabaseResponse, Error> {
+        let cluster_name = instance_name(project_name, engine);
+        let cluster = match self.find_cluster(&cluster_name).await? {
+            Some(cluster) => cluster,
+            None => return Err(Error::DatabaseNotFound(cluster_name)),
+        };
+
+        // Without a writer the cluster cannot be connected to yet
+        let writer = match self
+            .find_instance(&aurora_writer_name(&cluster_name))
+            .await?
+        {
+            Some(writer) => writer,
+            None => return Err(Error::DatabaseNotFound(cluster_name)),
+        };
+
+        let iam_auth = cluster
+            .iam_database_authentication_enabled
+            .unwrap_or_default();
+        let password = match self.stored_password(&cluster_name, iam_auth).await? {
+            Some(password) => password,
+            None => return Err(Error::CredentialsNotStored(cluster_name)),
+        };
+
+        self.aurora_response(project_name, engine, cluster, writer, password)
+            .await
+    }
+
+    /// Look up the Aurora cluster of a project without changing anything
+    async fn describe_aws_aurora(
+        &self,
+        project_name: &str,
+        engine: &aws_rds::Engine,
+    ) -> Result<Option<DatabaseInfo>, Error> {
+        let cluster_name = instance_name(project_name, engine);
+
+        Ok(self
+            .find_cluster(&cluster_name)
+            .await?
+            .map(|cluster| cluster_info(&cluster)))
+    }
+
+    /// Delete the Aurora cluster of a project and its writer, if they exist. A final snapshot of
+    /// the cluster is taken unless the request skips it, and its identifier is returned
+    async fn delete_aws_aurora(
+        &self,
+        project_name: &str,
+        engine: &aws_rds::Engine,
+    ) -> Result<Option<String>, Error> {
+        let client = self.rds_client.as_ref();
+        let cluster_name = instance_name(project_name, engine);
+        let _lock = self.lock_instance(&cluster_name).await;
+
+        let cluster = match self.find_cluster(&cluster_name).await? {
+            Some(cluster) => cluster,
+            None => return Ok(None),
+        };
+
+        if cluster.deletion_protection.unwrap_or_default() {
+            if !self.config.disable_deletion_protection {
+                return Err(Error::DeletionProtected(cluster_name));
+            }
+
+            info!("disabling deletion protection on {cluster_name}");
+
+            let modify = ModifyCluster {
+                identifier: cluster_name.clone(),
+                deletion_protection: Some(false),
+                apply_immediately: Some(true),
+                ..Default::default()
+            };
+            RETRY_BACKOFF
+                .retry(RETRY_ATTEMPTS, RdsError::is_retryable, || {
+                    client.modify_cluster(modify.clone())
+                })
+                .await?;
+
+            wait_for_cluster(
+                client,
+                &self.config,
+                &cluster_name,
+                InstanceStatus::Available,
+            )
+            .await?;
+        }
+
+        // A cluster cannot be deleted while it has instances. The data of the writer lives in the
+        // cluster, so it needs no snapshot of its own
+        let writer_name = aurora_writer_name(&cluster_name);
+        if self.find_instance(&writer_name).await?.is_some() {
+            info!("deleting writer {writer_name} of Aurora cluster {cluster_name}");
+
+            let deleted = client
+                .delete_instance(DeleteInstance {
+                    identifier: writer_name.clone(),
+                    final_snapshot: None,
+                })
+                .await;
+
+            match deleted {
+                Ok(()) | Err(RdsError::InstanceNotFound) => {}
+                Err(error) => return Err(error.into()),
+            }
+
+            wait_for_deletion(client, &self.config, &writer_name).await?;
+        }
+
+        let final_snapshot = if engine.config().skip_final_snapshot {
+            warn!("deleting Aurora cluster {cluster_name} without a final snapshot");
+            None
+        } else {
+            Some(final_snapshot_name(&cluster_name))
+        };
+
+        info!(?final_snapshot, "deleting Aurora cluster {cluster_name}");
+        let deleted = client
+            .delete_cluster(DeleteCluster {
+                identifier: cluster_name,
+                final_snapshot: final_snapshot.clone(),
+            })
+            .await;
+
+        match deleted {
+            Ok(()) => Ok(final_snapshot),
+            Err(RdsError::ClusterNotFound) => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Connection details of an Aurora cluster which is up. Clients connect to the endpoint of
+    /// the cluster, which points at `writer`
+    async fn aurora_response(
+        &self,
+        project_name: &str,
+        engine: &aws_rds::Engine,
+        cluster: DbCluster,
+        writer: DbInstance,
+        password: MasterPassword,
+    ) -> Result<DatabaseResponse, Error> {
+        let cluster_name = cluster
+            .db_cluster_identifier
+            .clone()
+            .expect("cluster to have an identifier");
+        let address = cluster.endpoint.expect("cluster to have a writer endpoint");
+        let port = match cluster.port {
+            Some(port) if port != 0 => port.to_string(),
+            _ => engine_spec(engine).port.to_string(),
+        };
+        let address_public = if writer.publicly_accessible {
+            self.config.public_address(&address)
+        } else {
+            String::new()
+        };
+
+        let iam_auth = cluster
+            .iam_database_authentication_enabled
+            .unwrap_or_default();
+        let (password, password_secret_arn) = match password {
+            MasterPassword::Reset(password) => {
+                let password = if iam_auth { String::new() } else { password };
+
+                self.store_password(project_name, &cluster_name, password, iam_auth)
+                    .await?
+            }
+            MasterPassword::Kept { secret_arn } => (String::new(), secret_arn),
+        };
+
+        Ok(DatabaseResponse {
+            engine: engine.to_string(),
+            username: cluster.master_username.expect("cluster to have a username"),
+            password,
+            database_name: cluster
+                .database_name
+                .expect("cluster to have a default database"),
+            address_private: address,
+            address_public,
+            port,
+            iam_auth,
+            ca_cert_identifier: writer.ca_certificate_identifier.unwrap_or_default(),
+            is_publicly_accessible: writer.publicly_accessible,
+            ca_bundle_url: RDS_CA_BUNDLE_URL.to_string(),
+            password_secret_arn,
+            // The cluster is what gets upgraded, and its instances follow
+            engine_version: cluster
+                .engine_version
+                .or(writer.engine_version)
+                .unwrap_or_default(),
+            ..Default::default()
+        })
+    }
+
+    /// Make sure `primary` has a read replica, returning the address of the replica
+    async fn read_replica(
+        &self,
+        primary: &DbInstance,
+        project_name: &str,
+    ) -> Result<String, Error> {
+        let client = self.rds_client.as_ref();
+        let primary_name = primary.db_instance_identifier.clone().unwrap_or_default();
+
+        if primary.backup_retention_period == 0 {
+            return Err(Error::ReadReplicaNeedsBackups(primary_name));
+        }
+
+        let replica_name = read_replica_name(&primary_name);
+        if self.find_instance(&replica_name).await?.is_none() {
+            info!("creating read replica {replica_name}");
+
+            let create = CreateReadReplica {
+                identifier: replica_name.clone(),
+                source: primary_name,
+                instance_class: primary.db_instance_class.clone().unwrap_or_default(),
+                publicly_accessible: primary.publicly_accessible,
+                tags: vec![Tag::builder().key(PROJECT_TAG).value(project_name).build()],
+            };
+            RETRY_BACKOFF
+                .retry(RETRY_ATTEMPTS, RdsError::is_retryable, || {
+                    client.create_read_replica(create.clone())
+                })
+                .await?;
+
+            wait_for_instance(
+                client,
+                &self.config,
+                &replica_name,
+                InstanceStatus::Creating,
+            )
+            .await
+            .map_err(creation_failed)?;
+        }
+
+        let replica = wait_for_instance(
+            client,
+            &self.config,
+            &replica_name,
+            InstanceStatus::Available,
+        )
+        .await?;
+
+        Ok(replica
+            .endpoint
+            .and_then(|endpoint| endpoint.address)
+            .expect("read replica to have an address"))
+    }
+
+    /// Get the instance class requested for `engine`, making sure it is allowed
+    fn instance_class(&self, engine: &aws_rds::Engine) -> Result<InstanceClass, Error> {
+        let requested = &engine.config().instance_class;
+        let class = if requested.is_empty() {
+            self.config.default_instance_class.clone()
+        } else {
+            requested.parse().map_err(Error::InvalidInstanceClass)?
+        };
+
+        self.config
+            .check_instance_class(&engine.to_string(), &class)?;
+
+        Ok(class)
+    }
+
+    /// Network of the environment `engine` asks for, or the default one when it names none
+    fn network(&self, engine: &aws_rds::Engine) -> Result<Network, Error> {
+        let environment = match &engine.config().environment {
+            Some(environment) => environment,
+            None => {
+                return Ok(Network {
+                    subnet_group: self.config.subnet_group.clone(),
+                    security_group_ids: self.config.security_group_ids.clone(),
+                })
+            }
+        };
+
+        self.config
+            .environments
+            .get(environment)
+            .cloned()
+            .ok_or_else(|| {
+                let mut known: Vec<_> = self.config.environments.keys().cloned().collect();
+                known.sort();
+
+                Error::UnknownEnvironment {
+                    environment: environment.clone(),
+                    known: known.join(", "),
+                }
+            })
+    }
+
+    /// Security groups requested for `engine`, falling back to the ones of its `network`
+    fn security_group_ids(
+        &self,
+        engine: &aws_rds::Engine,
+        network: &Network,
+    ) -> Option<Vec<String>> {
+        let requested = &engine.config().vpc_security_group_ids;
+        let ids = if requested.is_empty() {
+            &network.security_group_ids
+        } else {
+            requested
+        };
+
+        (!ids.is_empty()).then(|| ids.clone())
+    }
+
+    /// Decide if a new instance should be multi-AZ, making sure the subnet group can support it
+    async fn multi_az(&self, requested: bool, network: &Network) -> Result<bool, Error> {
+        if !requested {
+            return Ok(false);
+        }
+
+        let subnet_group_name = network
+            .subnet_group
+            .as_deref()
+            .unwrap_or(DEFAULT_VPC_SUBNET_GROUP);
+
+        let subnet_group = self
+            .rds_client
+            .describe_subnet_group(subnet_group_name)
+            .await?
+            .ok_or_else(|| {
+                Error::Plain(format!("subnet group '{subnet_group_name}' does not exist"))
+            })?;
+
+        match check_multi_az_subnets(&subnet_group) {
+            Ok(()) => Ok(true),
+            Err(error) if self.config.single_az_fallback => {
+                warn!(%error, "falling back to a single-AZ instance");
+                Ok(false)
+            }
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Provisioner for MyProvisioner {
+    async fn provision_database(
+        &self,
+        request: Request<DatabaseRequest>,
+    ) -> Result<Response<DatabaseResponse>, Status> {
+        let deadline = request_deadline(&request);
+        let reply = self.provision(request.into_inner(), deadline).await?;
+
+        Ok(Response::new(reply))
+    }
+
+    #[tracing::instrument(skip(self, request), fields(requests = request.get_ref().requests.len()))]
+    async fn provision_databases(
+        &self,
+        request: Request<ProvisionDatabasesRequest>,
+    ) -> Result<Response<ProvisionDatabasesResponse>, Status> {
+        // RDS requests for the same instance still wait on each other through its lock
+        let deadline = request_deadline(&request);
+        let results = stream::iter(request.into_inner().requests)
+            .map(|request| self.provision(request, deadline))
+            .buffered(self.config.provision_parallelism)
+            .map(|reply| {
+                let result = match reply {
+                    Ok(database) => provision_result::Result::Database(database),
+                    Err(status) => provision_result::Result::Error(ProvisionError {
+                        code: status.code() as i32,
+                        message: status.message().to_string(),
+                    }),
+                };
+
+                ProvisionResult {
+                    result: Some(result),
+                }
+            })
+            .collect()
+            .await;
+
+        Ok(Response::new(ProvisionDatabasesResponse { results }))
+    }
+
+    async fn supported_engines(
+        &self,
+        _request: Request<SupportedEnginesRequest>,
+    ) -> Result<Response<SupportedEnginesResponse>, Status> {
+        Ok(Response::new(SupportedEnginesResponse {
+            engines: self.engines(),
+        }))
+    }
+
+    #[tracing::instrument(
+        skip(self, request),
+        fields(
+            project_name = %request.get_ref().project_name,
+            correlation_id = field::Empty
+        )
+    )]
+    async fn delete_database(
+        &self,
+        request: Request<DatabaseRequest>,
+    ) -> Result<Response<DeleteDatabaseResponse>, Status> {
+        let mut request = request.into_inner();
+        correlate(&mut request);
+
+        let final_snapshot = match request.db_type.ok_or(Error::MissingDatabaseType)? {
+            DbType::Shared(shared) => {
+                self.delete_shared_db(&request.project_name, &shared)
+                    .await?;
+
+                None
+            }
+            DbType::SharedRedis(_) => {
+                self.delete_shared_redis(&request.project_name).await?;
+
+                None
+            }
+            DbType::AwsRds(AwsRds { engine }) => {
+                self.delete_aws_rds(&request.project_name, &rds_engine(engine)?)
+                    .await?
+            }
+        };
+
+        Ok(Response::new(DeleteDatabaseResponse {
+            final_snapshot: final_snapshot.unwrap_or_default(),
+        }))
+    }
+
+    #[tracing::instrument(skip(self, request), fields(project_name = %request.get_ref().project_name))]
+    async fn list_databases(
+        &self,
+        request: Request<ListDatabasesRequest>,
+    ) -> Result<Response<ListDatabasesResponse>, Status> {
+        let databases = self
+            .project_databases(&request.into_inner().project_name)
+            .await?;
+
+        Ok(Response::new(ListDatabasesResponse { databases }))
+    }
+
+    #[tracing::instrument(
+        skip(self, request),
+        fields(
+            project_name = %request.get_ref().project_name,
+            correlation_id = field::Empty
+        )
+    )]
+    async fn describe_database(
+        &self,
+        request: Request<DatabaseRequest>,
+    ) -> Result<Response<DescribeDatabaseResponse>, Status> {
+        let mut request = request.into_inner();
+        correlate(&mut request);
+
+        let database = match request.db_type.ok_or(Error::MissingDatabaseType)? {
+            DbType::Shared(shared) => {
+                self.describe_shared_db(&request.project_name, &shared)
+                    .await?
+            }
+            DbType::SharedRedis(_) => self.describe_shared_redis(&request.project_name).await?,
+            DbType::AwsRds(AwsRds { engine }) => {
+                self.describe_aws_rds(&request.project_name, &rds_engine(engine)?)
+                    .await?
+            }
+        };
+
+        Ok(Response::new(DescribeDatabaseResponse {
+            exists: database.is_some(),
+            database,
+        }))
+    }
+
+    #[tracing::instrument(
+        skip(self, request),
+        fields(
+            project_name = %request.get_ref().project_name,
+            correlation_id = field::Empty
+        )
+    )]
+    async fn rotate_credentials(
+        &self,
+        request: Request<DatabaseRequest>,
+    ) -> Result<Response<DatabaseResponse>, Status> {
+        let mut request = request.into_inner();
+        correlate(&mut request);
+
+        let mut reply = match request.db_type.ok_or(Error::MissingDatabaseType)? {
+            DbType::Shared(shared) => {
+                self.rotate_shared_db(&request.project_name, &shared)
+                    .await?
+            }
+            DbType::SharedRedis(_) => self.rotate_shared_redis(&request.project_name).await?,
+            DbType::AwsRds(AwsRds { engine }) => {
+                self.rotate_aws_rds(&request.project_name, &rds_engine(engine)?)
+                    .await?
+            }
+        };
+        reply.correlation_id = request.correlation_id;
+
+        Ok(Response::new(reply))
+    }
+
+    #[tracing::instrument(
+        skip(self, request),
+        fields(
+            project_name = %request.get_ref().project_name,
+            correlation_id = field::Empty
+        )
+    )]
+    async fn get_connection_info(
+        &self,
+        request: Request<DatabaseRequest>,
+    ) -> Result<Response<DatabaseResponse>, Status> {
+        let mut request = request.into_inner();
+        correlate(&mut request);
+
+        let mut reply =
+            match request.db_type.ok_or(Error::MissingDatabaseType)? {
+                DbType::Shared(shared) => {
+                    self.shared_db_connection_info(&request.project_name, &shared)
+                        .await?
+                }
+                DbType::SharedRedis(_) => return Err(Status::unimplemented(
+                    "passwords of shared Redis users are not stored, rotate them to get new ones",
+                )),
+                DbType::AwsRds(AwsRds { engine }) => {
+                    self.aws_rds_connection_info(&request.project_name, &rds_engine(engine)?)
+                        .await?
+                }
+            };
+        reply.correlation_id = request.correlation_id;
+
+        Ok(Response::new(reply))
+    }
+}
+
+/// Make sure `request` has a correlation id, generating one when the client left it out, and
+/// record it on the current span so every log of the request can be tied together
+fn correlate(request: &mut DatabaseRequest) {
+    if request.correlation_id.is_empty() {
+        request.correlation_id = Uuid::new_v4().to_string();
+    }
+    Span::current().record("correlation_id", &field::display(&request.correlation_id));
+}
+
+/// Engine of an RDS request. Clients can leave the oneof out, so it cannot be trusted to be set
+fn rds_engine(engine: Option<aws_rds::Engine>) -> Result<aws_rds::Engine, Error> {
+    engine.ok_or(Error::MissingEngine)
+}
+
+/// When the client stops waiting for a response to `request`, going by its `grpc-timeout` header
+fn request_deadline<T>(request: &Request<T>) -> Option<tokio::time::Instant> {
+    let timeout = request.metadata().get("grpc-timeout")?.to_str().ok()?;
+
+    parse_grpc_timeout(timeout).map(|timeout| tokio::time::Instant::now() + timeout)
+}
+
+/// Parse a `grpc-timeout` header, which is at most 8 digits followed by a unit
+fn parse_grpc_timeout(timeout: &str) -> Option<Duration> {
+    if timeout.len() < 2 || timeout.len() > 9 {
+        return None;
+    }
+
+    let (amount, unit) = timeout.split_at(timeout.len() - 1);
+    let amount: u64 = amount.parse().ok()?;
+
+    let timeout = match unit {
+        "H" => Duration::from_secs(amount * 60 * 60),
+        "M" => Duration::from_secs(amount * 60),
+        "S" => Duration::from_secs(amount),
+        "m" => Duration::from_millis(amount),
+        "u" => Duration::from_micros(amount),
+        "n" => Duration::from_nanos(amount),
+        _ => return None,
+    };
+
+    Some(timeout)
+}
+
+/// Project names end up in SQL identifiers and AWS resource names, so only allow a safe subset
+fn validate_project_name(project_name: &str) -> Result<(), Error> {
+    let is_valid = !project_name.is_empty()
+        && !project_name.starts_with('-')
+        && !project_name.ends_with('-')
+        && project_name
+            .bytes()
+            .all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-');
+
+    if is_valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidProjectName(project_name.to_string()))
+    }
+}
+
+/// Name for the snapshot taken of an instance right before it is deleted
+fn final_snapshot_name(instance_name: &str) -> String {
+    let timestamp = unix_secs(SystemTime::now());
+
+    format!("{instance_name}-final-{timestamp}")
+}
+
+/// Make sure only allowed extensions are asked for. Their names end up in SQL
+fn check_extensions(extensions: &[String]) -> Result<(), Error> {
+    match extensions
+        .iter()
+        .find(|extension| !ALLOWED_EXTENSIONS.contains(&extension.as_str()))
+    {
+        Some(extension) => Err(Error::ExtensionNotAllowed {
+            extension: extension.clone(),
+            allowed: ALLOWED_EXTENSIONS.join(", "),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Encoding and locale a shared request asks for
+fn db_locale(options: &Shared) -> DbLocale {
+    let setting = |value: &String| Some(value.clone()).filter(|value| !value.is_empty());
+
+    DbLocale {
+        encoding: setting(&options.encoding),
+        lc_collate: setting(&options.lc_collate),
+        lc_ctype: setting(&options.lc_ctype),
+    }
+}
+
+/// Get the name of the shared database to use for a project
+fn shared_db_name(db_prefix: &str, project_name: &str, options: &Shared) -> Result<String, Error> {
+    if options.database_name.is_empty() {
+        let name = format!("{db_prefix}{project_name}");
+        check_identifier_len(&name)?;
+
+        return Ok(name);
+    }
+
+    let name = &options.database_name;
+    let is_valid = name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name.bytes().all(|byte| {
+            byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-' || byte == b'_'
+        });
+
+    if !is_valid {
+        return Err(Error::InvalidDatabaseName(name.clone()));
+    }
+
+    // Default names of other projects would otherwise be up for grabs
+    if !db_prefix.is_empty() && name.starts_with(db_prefix) {
+        return Err(Error::ReservedDatabaseName {
+            name: name.clone(),
+            prefix: db_prefix.to_string(),
+        });
+    }
+
+    check_identifier_len(name)?;
+
+    Ok(name.clone())
+}
+
+/// Names of the extra roles a shared request asks for, which are the project's role name and
+/// their suffix joined by an underscore. Project names cannot have one, so the roles of one
+/// project never clash with those of another. The reader takes [READER_SUFFIX]
+fn extra_role_names(username: &str, roles: &[DatabaseRole]) -> Result<Vec<String>, Error> {
+    let mut names = Vec::new();
+
+    for role in roles {
+        let suffix = &role.suffix;
+        let is_valid = !suffix.is_empty()
+            && suffix
+                .bytes()
+                .all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'_');
+        let name = format!("{username}_{suffix}");
+
+        if !is_valid || suffix == READER_SUFFIX || names.contains(&name) {
+            return Err(Error::InvalidRoleSuffix(suffix.clone()));
+        }
+
+        check_identifier_len(&name)?;
+        names.push(name);
+    }
+
+    Ok(names)
+}
+
+/// Schemas of a shared DB other roles get privileges in
+fn shared_schemas<'a>(project_name: &'a str, options: &Shared) -> Vec<&'a str> {
+    let mut schemas = vec!["public"];
+    if options.dedicated_schema {
+        schemas.push(project_name);
+    }
+
+    schemas
+}
+
+/// Whether the shared Redis has an ACL user called `username`
+async fn redis_user_exists(
+    connection: &mut redis::aio::Connection,
+    username: &str,
+) -> Result<bool, Error> {
+    let matching_user: redis::Value = redis::cmd("ACL")
+        .arg("GETUSER")
+        .arg(username)
+        .query_async(connection)
+        .await?;
+
+    Ok(!matches!(matching_user, redis::Value::Nil))
+}
+
+/// Create or update the ACL user `username` with a new password, limited to its own keys.
+/// Returns the new password
+async fn set_redis_user(
+    connection: &mut redis::aio::Connection,
+    username: &str,
+) -> Result<String, Error> {
+    let password = generate_password();
+
+    redis::cmd("ACL")
+        .arg("SETUSER")
+        .arg(username)
+        .arg("on")
+        .arg("resetpass")
+        .arg(format!(">{password}"))
+        .arg("resetkeys")
+        .arg(format!("~{}*", redis_key_prefix(username)))
+        .arg("+@all")
+        .arg("-@dangerous")
+        .query_async::<_, ()>(connection)
+        .await?;
+
+    Ok(password)
+}
+
+/// What every key of the shared Redis user `username` starts with
+fn redis_key_prefix(username: &str) -> String {
+    format!("{username}:")
+}
+
+/// Name of the read-only role of a project on the shared Postgres, next to its extra roles. It
+/// shares the role prefix, so environments sharing a server keep their readers apart
+fn shared_reader_name(username: &str) -> Result<String, Error> {
+    let name = format!("{username}_{READER_SUFFIX}");
+    check_identifier_len(&name)?;
+
+    Ok(name)
+}
+
+/// Make sure Postgres will keep all of `name` instead of truncating it
+fn check_identifier_len(name: &str) -> Result<(), Error> {
+    if name.len() > MAX_IDENTIFIER_LEN {
+        return Err(Error::NameTooLong {
+            name: name.to_string(),
+            max: MAX_IDENTIFIER_LEN,
+        });
+    }
+
+    Ok(())
+}
+
+fn generate_password() -> String {
+    generate_password_with(&mut rand::thread_rng())
+}
+
+/// Generate a password from `rng`, so tests can use a seeded one
+fn generate_password_with(rng: &mut impl Rng) -> String {
+    rng.sample_iter(&rand::distributions::Alphanumeric)
+        .take(12)
+        .map(char::from)
+        .collect()
+}
+
+/// Where something being waited on is at
+enum Polled<T> {
+    Done(T),
+
+    /// Not done yet, and in this status
+    Pending(String),
+}
+
+/// Check on something until it is done, backing off between checks as configured. Gives up once
+/// the configured deadline passes
+async fn poll<T, F, Fut>(
+    config: &Config,
+    name: &str,
+    wanted: &str,
+    mut check: F,
+) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Polled<T>, Error>>,
+{
+    let backoff = Backoff {
+        initial: config.rds_poll_initial_interval,
+        max: config.rds_poll_max_interval,
+    };
+    let mut delays = backoff.delays();
+    let start = tokio::time::Instant::now();
+
+    loop {
+        let status = match check().await? {
+            Polled::Done(done) => return Ok(done),
+            Polled::Pending(status) => status,
+        };
+
+        if start.elapsed() >= config.rds_wait_timeout {
+            return Err(Error::WaitTimedOut {
+                instance: name.to_string(),
+                status,
+                wanted: wanted.to_string(),
+            });
+        }
+
+        sleep(delays.next().expect("delays to be endless")).await;
+    }
+}
+
+#[tracing::instrument(skip_all, fields(instance_name = name, %wait_for))]
+async fn wait_for_instance(
+    client: &dyn RdsClient,
+    config: &Config,
+    name: &str,
+    wait_for: InstanceStatus,
+) -> Result<DbInstance, Error> {
+    debug!("waiting for {name} to enter {wait_for} state");
+
+    poll(config, name, wait_for.as_str(), || async {
+        let instance = client.describe_instance(name).await?;
+
+        let status: InstanceStatus = instance
+            .db_instance_status
+            .as_deref()
+            .expect("instance to have a status")
+            .into();
+
+        if status.reached(&wait_for) {
+            return Ok(Polled::Done(instance));
+        }
+
+        // A full instance can take a moment to pick up a storage increase, so only give up on it
+        // once it should be available
+        if status == InstanceStatus::StorageFull && wait_for == InstanceStatus::Available {
+            return Err(Error::StorageFull {
+                instance: name.to_string(),
+                allocated_storage: instance.allocated_storage,
+            });
+        }
+
+        if status.is_failed() {
+            return Err(Error::InstanceFailed {
+                instance: name.to_string(),
+                status: status.to_string(),
+            });
+        }
+
+        Ok(Polled::Pending(status.to_string()))
+    })
+    .await
+}
+
+/// Wait for a modification of `name` to start. Most changes show up as `modifying`, a password
+/// reset on its own as `resetting-master-credentials`, and some as yet another status, so anything
+/// but `available` counts. An instance which stays available past [MODIFICATION_GRACE] is taken to
+/// be done already
+#[tracing::instrument(skip_all, fields(instance_name = name))]
+async fn wait_for_modification<F, Fut>(
+    config: &Config,
+    name: &str,
+    mut status: F,
+) -> Result<(), Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<InstanceStatus, Error>>,
+{
+    debug!("waiting for modification of {name} to start");
+    let start = tokio::time::Instant::now();
+
+    poll(config, name, InstanceStatus::Modifying.as_str(), || {
+        let status = status();
+        async move {
+            let status = status.await?;
+
+            if status.is_failed() {
+                return Err(Error::InstanceFailed {
+                    instance: name.to_string(),
+                    status: status.to_string(),
+                });
+            }
+
+            if status != InstanceStatus::Available || start.elapsed() >= MODIFICATION_GRACE {
+                return Ok(Polled::Done(()));
+            }
+
+            Ok(Polled::Pending(status.to_string()))
+        }
+    })
+    .await
+}
+
+async fn instance_status(client: &dyn RdsClient, name: &str) -> Result<InstanceStatus, Error> {
+    let instance = client.describe_instance(name).await?;
+
+    Ok(instance
+        .db_instance_status
+        .as_deref()
+        .expect("instance to have a status")
+        .into())
+}
+
+async fn cluster_status(client: &dyn RdsClient, name: &str) -> Result<InstanceStatus, Error> {
+    let cluster = client.describe_cluster(name).await?;
+
+    Ok(cluster
+        .status
+        .as_deref()
+        .expect("cluster to have a status")
+        .into())
+}
+
+/// Wait for an Aurora cluster to reach a status. Clusters go through the same statuses as
+/// instances
+#[tracing::instrument(skip_all, fields(cluster_name = name, %wait_for))]
+async fn wait_for_cluster(
+    client: &dyn RdsClient,
+    config: &Config,
+    name: &str,
+    wait_for: InstanceStatus,
+) -> Result<DbCluster, Error> {
+    debug!("waiting for cluster {name} to enter {wait_for} state");
+
+    poll(config, name, wait_for.as_str(), || async {
+        let cluster = client.describe_cluster(name).await?;
+
+        let status: InstanceStatus = cluster
+            .status
+            .as_deref()
+            .expect("cluster to have a status")
+            .into();
+
+        if status.reached(&wait_for) {
+            return Ok(Polled::Done(cluster));
+        }
+
+        if status.is_failed() {
+            return Err(Error::InstanceFailed {
+                instance: name.to_string(),
+                status: status.to_string(),
+            });
+        }
+
+        Ok(Polled::Pending(status.to_string()))
+    })
+    .await
+}
+
+/// Wait for instance `name` to be available with its deletion protection turned off
+#[tracing::instrument(skip_all, fields(instance_name = name))]
+async fn wait_for_unprotected(
+    client: &dyn RdsClient,
+    config: &Config,
+    name: &str,
+) -> Result<(), Error> {
+    poll(config, name, InstanceStatus::Available.as_str(), || async {
+        let instance = client.describe_instance(name).await?;
+
+        let status: InstanceStatus = instance
+            .db_instance_status
+            .as_deref()
+            .expect("instance to have a status")
+            .into();
+
+        if status.reached(&InstanceStatus::Available) && !instance.deletion_protection {
+            return Ok(Polled::Done(()));
+        }
+
+        if status.is_failed() {
+            return Err(Error::InstanceFailed {
+                instance: name.to_string(),
+                status: status.to_string(),
+            });
+        }
+
+        Ok(Polled::Pending(status.to_string()))
+    })
+    .await
+}
+
+/// Wait for an instance to be gone after it was deleted
+#[tracing::instrument(skip_all, fields(instance_name = name))]
+async fn wait_for_deletion(
+    client: &dyn RdsClient,
+    config: &Config,
+    name: &str,
+) -> Result<(), Error> {
+    poll(config, name, "deleted", || async {
+        match client.describe_instance(name).await {
+            Ok(instance) => Ok(Polled::Pending(
+                instance.db_instance_status.unwrap_or_default(),
+            )),
+            Err(RdsError::InstanceNotFound) => Ok(Polled::Done(())),
+            Err(error) => Err(error.into()),
+        }
+    })
+    .await
+}
+
+/// Restore an instance from a snapshot, then reset the password it got from the snapshot
+async fn restore_instance(
+    client: &dyn RdsClient,
+    config: &Config,
+    restore: RestoreInstance,
+    reset: ModifyInstance,
+) -> Result<(), Error> {
+    let instance_name = restore.identifier.clone();
+
+    RETRY_BACKOFF
+        .retry(RETRY_ATTEMPTS, RdsError::is_retryable, || {
+            client.restore_instance(restore.clone())
+        })
+        .await?;
+
+    wait_for_instance(client, config, &instance_name, InstanceStatus::Creating)
+        .await
+        .map_err(creation_failed)?;
+    wait_for_instance(client, config, &instance_name, InstanceStatus::Available)
+        .await
+        .map_err(creation_failed)?;
+
+    RETRY_BACKOFF
+        .retry(RETRY_ATTEMPTS, RdsError::is_retryable, || {
+            client.modify_instance(reset.clone())
+        })
+        .await?;
+
+    wait_for_modification(config, &instance_name, || {
+        instance_status(client, &instance_name)
+    })
+    .await
+    .map_err(creation_failed)?;
+
+    Ok(())
+}
+
+/// Reprovisioning something the project already `owned` is always fine, but new resources have
+/// to fit under `limit`
+fn check_quota(
+    project_name: &str,
+    kind: &'static str,
+    owned: &[String],
+    requested: &str,
+    limit: usize,
+) -> Result<(), Error> {
+    if owned.len() >= limit && !owned.iter().any(|name| name == requested) {
+        return Err(Error::QuotaExceeded {
+            project: project_name.to_string(),
+            kind,
+            limit,
+        });
+    }
+
+    Ok(())
+}
+
+/// Mark an instance failure as happening while the instance was being created
+fn creation_failed(error: Error) -> Error {
+    match error {
+        Error::InstanceFailed { instance, status } => {
+            Error::InstanceCreationFailed { instance, status }
+        }
+        error => error,
+    }
+}
+
+/// Multi-AZ instances need a subnet group spanning at least two availability zones
+fn check_multi_az_subnets(subnet_group: &DbSubnetGroup) -> Result<(), Error> {
+    let zones: HashSet<_> = subnet_group
+        .subnets
+        .iter()
+        .flatten()
+        .filter_map(|subnet| subnet.subnet_availability_zone.as_ref()?.name.as_deref())
+        .collect();
+
+    if zones.len() < 2 {
+        return Err(Error::InsufficientAvailabilityZones {
+            subnet_group: subnet_group
+                .db_subnet_group_name
+                .clone()
+                .unwrap_or_default(),
+            zones: zones.len(),
+        });
+    }
+
+    Ok(())
+}
+
+fn instance_name(project_name: &str, engine: &aws_rds::Engine) -> String {
+    rds_identifier(&format!("{}-{}", project_name, engine))
+}
+
+/// Identifier of the read replica of `instance_name`
+fn read_replica_name(instance_name: &str) -> String {
+    rds_identifier(&format!("{instance_name}-replica"))
+}
+
+/// Turn `name` into an identifier RDS accepts: at most 63 lowercase letters, digits and single
+/// hyphens, starting with a letter and not ending with a hyphen. Names which are valid already
+/// are kept as they are. Anything cut off is replaced by a hash of the whole name, so the same
+/// name always maps to the same identifier and long names sharing a prefix do not collide
+fn rds_identifier(name: &str) -> String {
+    let mut identifier = String::with_capacity(name.len());
+    for c in name.trim().chars() {
+        let c = c.to_ascii_lowercase();
+        if c.is_ascii_lowercase() || c.is_ascii_digit() {
+            identifier.push(c);
+        } else if !identifier.ends_with('-') {
+            identifier.push('-');
+        }
+    }
+
+    let mut identifier = identifier.trim_matches('-').to_string();
+    if !identifier.starts_with(|c: char| c.is_ascii_lowercase()) {
+        identifier.insert_str(0, "db-");
+    }
+
+    if identifier.len() > MAX_RDS_IDENTIFIER_LEN {
+        let hash = format!("{:016x}", fnv1a(name.as_bytes()));
+        let kept = &identifier[..MAX_RDS_IDENTIFIER_LEN - hash.len() - 1];
+
+        identifier = format!("{}-{hash}", kept.trim_end_matches('-'));
+    }
+
+    identifier.trim_end_matches('-').to_string()
+}
+
+/// 64 bit FNV-1a hash. Unlike the hasher in std, it is the same on every build, which matters
+/// for names of resources which outlive a single run of the provisioner
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Name of the secret the master password of `instance_name` is kept in
+fn password_secret_name(instance_name: &str) -> String {
+    format!("{PASSWORD_SECRET_PREFIX}{instance_name}")
+}
+
+/// Name of the secret the password of the shared Postgres role `username` is kept in
+fn shared_password_secret_name(username: &str) -> String {
+    format!("{SHARED_PASSWORD_SECRET_PREFIX}{username}")
+}
+
+/// Check if `modify` would leave `instance` as it is, apart from its password
+fn changes_nothing(modify: &ModifyInstance, instance: &DbInstance) -> bool {
+    let unchanged = |requested: Option<bool>, current: bool| {
+        requested.map_or(true, |requested| requested == current)
+    };
+    let security_groups_unchanged = modify
+        .security_group_ids
+        .as_ref()
+        .map_or(true, |requested| {
+            let current: HashSet<_> = instance
+                .vpc_security_groups
+                .iter()
+                .flatten()
+                .filter_map(|group| group.vpc_security_group_id.as_deref())
+                .collect();
+
+            requested.iter().map(String::as_str).collect::<HashSet<_>>() == current
+        });
+
+    modify.instance_class.is_none()
+        && modify.allocated_storage.is_none()
+        && unchanged(modify.multi_az, instance.multi_az)
+        && unchanged(modify.deletion_protection, instance.deletion_protection)
+        && security_groups_unchanged
+        && unchanged(modify.publicly_accessible, instance.publicly_accessible)
+        && unchanged(
+            modify.iam_auth,
+            instance.iam_database_authentication_enabled,
+        )
+        && modify.performance_insights.is_none()
+        && modify.performance_insights_retention.is_none()
+        && modify.parameter_group.is_none()
+        && modify.ca_certificate.is_none()
+        && (modify.preferred_maintenance_window.is_none()
+            || modify.preferred_maintenance_window == instance.preferred_maintenance_window)
+        && (modify.preferred_backup_window.is_none()
+            || modify.preferred_backup_window == instance.preferred_backup_window)
+}
+
+/// The same engine as `engine`, in the shape the username rules of the common crate take
+fn common_engine(engine: &aws_rds::Engine) -> AwsRdsEngine {
+    match engine {
+        aws_rds::Engine::Postgres(_) => AwsRdsEngine::Postgres,
+        aws_rds::Engine::Mysql(_) => AwsRdsEngine::MySql,
+        aws_rds::Engine::Mariadb(_) => AwsRdsEngine::MariaDB,
+    }
+}
+
+/// Identifier of the writer instance of the Aurora cluster `cluster_name`
+fn aurora_writer_name(cluster_name: &str) -> String {
+    rds_identifier(&format!("{cluster_name}-writer"))
+}
+
+/// Aurora engine which is compatible with `engine`, if there is one
+fn aurora_engine(engine: &aws_rds::Engine) -> Result<&'static str, Error> {
+    match engine {
+        aws_rds::Engine::Postgres(_) => Ok("aurora-postgresql"),
+        aws_rds::Engine::Mysql(_) => Ok("aurora-mysql"),
+        aws_rds::Engine::Mariadb(_) => Err(Error::AuroraNotSupported(engine.to_string())),
+    }
+}
+
+/// Get the class an existing instance should be resized to, if it was asked for a different one
+fn check_resize(
+    instance: &DbInstance,
+    config: &RdsConfig,
+    requested: &InstanceClass,
+) -> Result<Option<String>, Error> {
+    // Only an explicitly requested class can differ, the default never resizes an instance
+    if config.instance_class.is_empty() {
+        return Ok(None);
+    }
+
+    let requested = requested.to_string();
+    let current = instance.db_instance_class.clone().unwrap_or_default();
+    if current == requested {
+        return Ok(None);
+    }
+
+    if !config.allow_resize {
+        return Err(Error::ResizeNotAllowed {
+            instance: instance.db_instance_identifier.clone().unwrap_or_default(),
+            current,
+            requested,
+        });
+    }
+
+    Ok(Some(requested))
+}
+
+/// Make sure an instance of `class` supports every option `config` turns on, listing all of those
+/// it does not support at once
+fn check_capabilities(config: &RdsConfig, class: &str) -> Result<(), Error> {
+    // Leave classes we don't understand for AWS to judge
+    let class = match class.parse::<InstanceClass>() {
+        Ok(class) => class,
+        Err(_) => return Ok(()),
+    };
+
+    let requested = [
+        (
+            Feature::PerformanceInsights,
+            config.enable_performance_insights == Some(true),
+        ),
+        (Feature::MultiAz, config.multi_az == Some(true)),
+        (Feature::IamAuth, config.enable_iam_auth == Some(true)),
+        (
+            Feature::Io1Storage,
+            config.storage_type.as_deref() == Some("io1"),
+        ),
+    ];
+    let unsupported: Vec<_> = requested
+        .into_iter()
+        .filter(|(feature, is_requested)| *is_requested && !class.supports(*feature))
+        .map(|(feature, _)| format!("{feature} (needs at least a {} class)", feature.min_size()))
+        .collect();
+
+    if unsupported.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::UnsupportedOptions {
+            class: class.to_string(),
+            options: unsupported.join(", "),
+        })
+    }
+}
+
+/// Make sure Performance Insights can be turned on with the requested retention period
+fn check_performance_insights(config: &RdsConfig) -> Result<(), Error> {
+    if config.enable_performance_insights != Some(true) {
+        return Ok(());
+    }
+
+    match config.performance_insights_retention_period {
+        None | Some(7) | Some(731) => Ok(()),
+        Some(days) if days > 0 && days < 731 && days % 31 == 0 => Ok(()),
+        Some(days) => Err(Error::InvalidPerformanceInsights(format!(
+            "a retention period of {days} days is not allowed, use 7, 731 or a multiple of 31"
+        ))),
+    }
+}
+
+/// Get the storage type and provisioned IOPS requested in `config`, making sure they go together
+fn storage(config: &RdsConfig) -> Result<(Option<String>, Option<i32>), Error> {
+    let storage_type = config.storage_type.as_deref();
+    if let Some(storage_type) = storage_type {
+        if !["gp2", "gp3", "io1"].contains(&storage_type) {
+            return Err(Error::InvalidStorage(format!(
+                "'{storage_type}' is not a supported storage type, use gp2, gp3 or io1"
+            )));
+        }
+    }
+
+    match (storage_type, config.iops) {
+        (Some("io1"), None) => Err(Error::InvalidStorage(
+            "io1 storage needs provisioned IOPS".to_string(),
+        )),
+        (None | Some("gp2"), Some(_)) => Err(Error::InvalidStorage(
+            "IOPS can only be provisioned for io1 and gp3 storage".to_string(),
+        )),
+        (_, Some(iops)) if iops <= 0 => Err(Error::InvalidStorage(format!(
+            "{iops} is not a valid number of IOPS"
+        ))),
+        _ => Ok((config.storage_type.clone(), config.iops)),
+    }
+}
+
+/// Get the maintenance and backup windows requested in `config`, making sure they are in the
+/// formats AWS takes
+fn preferred_windows(config: &RdsConfig) -> Result<(Option<String>, Option<String>), Error> {
+    if let Some(window) = &config.preferred_maintenance_window {
+        if !is_window(window, true) {
+            return Err(Error::InvalidWindow {
+                window: window.clone(),
+                expected: "ddd:hh24:mi-ddd:hh24:mi",
+            });
+        }
+    }
+
+    if let Some(window) = &config.preferred_backup_window {
+        if !is_window(window, false) {
+            return Err(Error::InvalidWindow {
+                window: window.clone(),
+                expected: "hh24:mi-hh24:mi",
+            });
+        }
+    }
+
+    Ok((
+        config.preferred_maintenance_window.clone(),
+        config.preferred_backup_window.clone(),
+    ))
+}
+
+/// Check if `window` is a range of times like '03:00-04:00', with days like 'sun:23:00' when
+/// `with_day` is set
+fn is_window(window: &str, with_day: bool) -> bool {
+    const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+    let is_time = |time: &str| {
+        let time = if with_day {
+            match time.split_once(':') {
+                Some((day, time)) if DAYS.contains(&day) => time,
+                _ => return false,
+            }
+        } else {
+            time
+        };
+
+        let two_digits = |part: &str, max: u8| {
+            part.len() == 2
+                && part.bytes().all(|byte| byte.is_ascii_digit())
+                && part.parse::<u8>().map_or(false, |value| value <= max)
+        };
+
+        match time.split_once(':') {
+            Some((hours, minutes)) => two_digits(hours, 23) && two_digits(minutes, 59),
+            None => false,
+        }
+    };
+
+    match window.split_once('-') {
+        Some((start, end)) => is_time(start) && is_time(end),
+        None => false,
+    }
+}
+
+/// Get the parameter group requested in `config`, making sure it is a valid name
+fn parameter_group(config: &RdsConfig) -> Result<Option<String>, Error> {
+    let name = match &config.db_parameter_group_name {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+
+    // Letters, digits and single dashes, starting with a letter
+    let is_valid = name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.len() <= 255
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        && !name.ends_with('-')
+        && !name.contains("--");
+
+    if !is_valid {
+        return Err(Error::InvalidParameterGroup(name.clone()));
+    }
+
+    Ok(Some(name.clone()))
+}
+
+/// Get the logs requested to be exported to CloudWatch, making sure the engine has them
+fn cloudwatch_logs_exports(engine: &aws_rds::Engine) -> Result<Option<Vec<String>>, Error> {
+    let logs = &engine.config().cloudwatch_logs_exports;
+    if logs.is_empty() {
+        return Ok(None);
+    }
+
+    let supported: &[&str] = match engine {
+        aws_rds::Engine::Postgres(_) => &["postgresql", "upgrade"],
+        aws_rds::Engine::Mysql(_) | aws_rds::Engine::Mariadb(_) => {
+            &["audit", "error", "general", "slowquery"]
+        }
+    };
+
+    match logs.iter().find(|log| !supported.contains(&log.as_str())) {
+        Some(log) => Err(Error::InvalidLogExport {
+            log: log.clone(),
+            engine: engine.to_string(),
+            supported: supported.join(", "),
+        }),
+        None => Ok(Some(logs.clone())),
+    }
+}
+
+/// Check if deletion protection stands in the way of deleting an instance. Returns whether
+/// protection has to be turned off first
+fn check_deletion_protection(instance: &DbInstance, allow_disable: bool) -> Result<bool, Error> {
+    match (instance.deletion_protection, allow_disable) {
+        (false, _) => Ok(false),
+        (true, true) => Ok(true),
+        (true, false) => Err(Error::DeletionProtected(
+            instance.db_instance_identifier.clone().unwrap_or_default(),
+        )),
+    }
+}
+
+/// Check if an RDS instance is stuck in a state it will not recover from by itself
+fn is_stuck(instance: &DbInstance) -> bool {
+    instance
+        .db_instance_status
+        .as_deref()
+        .map_or(false, |status| InstanceStatus::from(status).is_failed())
+}
+
+/// Check if an RDS instance was created for `project_name`
+fn is_tagged_for(instance: &DbInstance, project_name: &str) -> bool {
+    project_tag(instance) == Some(project_name)
+}
+
+/// Project `instance` was made for, if it was made by the provisioner
+fn project_tag(instance: &DbInstance) -> Option<&str> {
+    instance
+        .tag_list
+        .iter()
+        .flatten()
+        .find(|tag| tag.key.as_deref() == Some(PROJECT_TAG))
+        .and_then(|tag| tag.value.as_deref())
+}
+
+fn instance_info(instance: &DbInstance) -> DatabaseInfo {
+    let endpoint = instance.endpoint.as_ref();
+
+    DatabaseInfo {
+        kind: "aws_rds".to_string(),
+        engine: instance.engine.clone().unwrap_or_default(),
+        name: instance.db_instance_identifier.clone().unwrap_or_default(),
+        status: instance.db_instance_status.clone().unwrap_or_default(),
+        address: endpoint
+            .and_then(|endpoint| endpoint.address.clone())
+            .unwrap_or_default(),
+        port: endpoint
+            .map(|endpoint| endpoint.port.to_string())
+            .unwrap_or_default(),
+        create_time: instance
+            .instance_create_time
+            .map(|time| time.secs())
+            .unwrap_or_default(),
+    }
+}
+
+fn cluster_info(cluster: &DbCluster) -> DatabaseInfo {
+    DatabaseInfo {
+        kind: "aws_rds".to_string(),
+        engine: cluster.engine.clone().unwrap_or_default(),
+        name: cluster.db_cluster_identifier.clone().unwrap_or_default(),
+        status: cluster.status.clone().unwrap_or_default(),
+        address: cluster.endpoint.clone().unwrap_or_default(),
+        port: cluster
+            .port
+            .map(|port| port.to_string())
+            .unwrap_or_default(),
+        create_time: cluster
+            .cluster_create_time
+            .map(|time| time.secs())
+            .unwrap_or_default(),
+    }
+}
+
+/// Seconds from the Unix epoch to `time`
+fn unix_secs(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .expect("time to be after the epoch")
+        .as_secs() as i64
+}
+
+/// Master password to hand out with the connection details of an instance
+enum MasterPassword {
+    /// The password was just reset to this
+    Reset(String),
+
+    /// The password was left as it is. Clients either use IAM auth, or get it from the secret
+    /// with this ARN
+    Kept { secret_arn: String },
+}
+
+type InstanceLocks = Arc<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>>;
+
+/// Held while working on an RDS instance. Its entry in the locks is removed once nobody holds or
+/// waits for it anymore, so the locks do not grow with every instance ever worked on
+struct InstanceLock {
+    guard: Option<OwnedMutexGuard<()>>,
+    instance_name: String,
+    locks: InstanceLocks,
+}
+
+impl InstanceLock {
+    async fn acquire(locks: &InstanceLocks, instance_name: &str) -> Self {
+        let lock = locks
+            .lock()
+            .expect("instance locks to not be poisoned")
+            .entry(instance_name.to_string())
+            .or_default()
+            .clone();
+
+        Self {
+            guard: Some(lock.lock_owned().await),
+            instance_name: instance_name.to_string(),
+            locks: locks.clone(),
+        }
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        // New waiters clone the lock while holding the map, so none can show up between the
+        // count being checked and the entry being removed
+        let mut locks = self
+            .locks
+            .lock()
+            .expect("instance locks to not be poisoned");
+        self.guard.take();
+
+        let unused = locks
+            .get(&self.instance_name)
+            .map_or(false, |lock| Arc::strong_count(lock) == 1);
+        if unused {
+            locks.remove(&self.instance_name);
+        }
+    }
+}
+
+/// How an engine is set up on RDS
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct EngineSpec {
+    /// Name AWS knows the engine by
+    engine_str: &'static str,
+
+    /// Database new instances are created with
+    default_db_name: &'static str,
+
+    /// Port clients connect to unless the instance says otherwise
+    port: u16,
+}
+
+/// Everything engine specific in one place, so a new engine only has to be added here
+fn engine_spec(engine: &aws_rds::Engine) -> EngineSpec {
+    match engine {
+        aws_rds::Engine::Postgres(_) => EngineSpec {
+            engine_str: "postgres",
+            default_db_name: "postgres",
+            port: POSTGRES_PORT,
+        },
+        aws_rds::Engine::Mysql(_) => EngineSpec {
+            engine_str: "mysql",
+            default_db_name: "mysql",
+            port: MYSQL_PORT,
+        },
+        aws_rds::Engine::Mariadb(_) => EngineSpec {
+            engine_str: "mariadb",
+            default_db_name: "mariadb",
+            port: MYSQL_PORT,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_rds::model::{AvailabilityZone, Subnet};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    fn subnet_group(zones: &[&str]) -> DbSubnetGroup {
+        let subnets = zones
+            .iter()
+            .map(|zone| {
+                Subnet::builder()
+                    .subnet_availability_zone(AvailabilityZone::builder().name(*zone).build())
+                    .build()
+            })
+            .collect();
+
+        DbSubnetGroup::builder()
+            .db_subnet_group_name("shuttle_rds")
+            .set_subnets(Some(subnets))
+            .build()
+    }
+
+    #[test]
+    fn rds_identifiers() {
+        assert_eq!(rds_identifier("my-project-postgres"), "my-project-postgres");
+        assert_eq!(rds_identifier(" My_Project--1 "), "my-project-1");
+        assert_eq!(rds_identifier("1st-project-mysql"), "db-1st-project-mysql");
+        assert_eq!(rds_identifier("--"), "db");
+
+        let long = format!("{}-postgres", "a".repeat(80));
+        let identifier = rds_identifier(&long);
+        assert_eq!(identifier.len(), MAX_RDS_IDENTIFIER_LEN);
+        assert_eq!(identifier, rds_identifier(&long));
+        assert_ne!(
+            identifier,
+            rds_identifier(&format!("{}-mysql", "a".repeat(80)))
+        );
+        assert!(identifier.starts_with(&"a".repeat(46)));
+
+        for name in [identifier, rds_identifier(&format!("{}-a", "b".repeat(62)))] {
+            assert!(name.len() <= MAX_RDS_IDENTIFIER_LEN);
+            assert!(!name.ends_with('-') && !name.contains("--"));
+        }
+    }
+
+    #[test]
+    fn passwords() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let first = generate_password_with(&mut rng);
+        let second = generate_password_with(&mut rng);
+
+        assert_eq!(first.len(), 12);
+        assert!(first.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(first, second);
+
+        // The same seed gives the same passwords
+        let mut rng = StdRng::seed_from_u64(42);
+        assert_eq!(generate_password_with(&mut rng), first);
+    }
+
+    #[test]
+    fn project_names() {
+        for name in ["my-project", "project1", "1"] {
+            assert!(validate_project_name(name).is_ok(), "{name:?} was err");
+        }
+
+        for name in [
+            "",
+            "-dash",
+            "dash-",
+            "Upper",
+            "under_score",
+            "new\"; CREATE ROLE \"injected",
+            "x\"; DROP DATABASE postgres; --",
+            "quote'",
+            "back\\slash",
+            "semi;colon",
+        ] {
+            assert!(
+                matches!(
+                    validate_project_name(name),
+                    Err(Error::InvalidProjectName(_))
+                ),
+                "{name:?} was ok"
+            );
+        }
+    }
+
+    #[test]
+    fn long_names() {
+        // "db-" and 61 characters make 64 bytes, one more than Postgres keeps
+        assert!(matches!(
+            shared_db_name("db-", &"a".repeat(61), &Shared::default()),
+            Err(Error::NameTooLong { max: 63, .. })
+        ));
+        assert_eq!(
+            shared_db_name("db-", &"a".repeat(60), &Shared::default())
+                .unwrap()
+                .len(),
+            63
+        );
+
+        let options = Shared {
+            database_name: "a".repeat(64),
+            ..Default::default()
+        };
+        assert!(matches!(
+            shared_db_name("db-", "project", &options),
+            Err(Error::NameTooLong { .. })
+        ));
+    }
+
+    #[test]
+    fn database_names() {
+        let name = |database_name: &str| {
+            shared_db_name(
+                "db-",
+                "project",
+                &Shared {
+                    database_name: database_name.to_string(),
+                    ..Default::default()
+                },
+            )
+        };
+
+        assert_eq!(name("").unwrap(), "db-project");
+        assert_eq!(name("my_app-db2").unwrap(), "my_app-db2");
+
+        // The default name of this or any other project
+        for reserved in ["db-project", "db-other"] {
+            assert!(
+                matches!(name(reserved), Err(Error::ReservedDatabaseName { .. })),
+                "{reserved:?} was ok"
+            );
+        }
+
+        for invalid in [
+            "2db",
+            "-db",
+            "Db",
+            "db\"; DROP DATABASE postgres; --",
+            "db'",
+        ] {
+            assert!(
+                matches!(name(invalid), Err(Error::InvalidDatabaseName(_))),
+                "{invalid:?} was ok"
+            );
+        }
+    }
+
+    #[test]
+    fn deletion_protection() {
+        let protected = DbInstance::builder()
+            .db_instance_identifier("protected-postgres")
+            .deletion_protection(true)
+            .build();
+        let unprotected = DbInstance::builder()
+            .db_instance_identifier("unprotected-postgres")
+            .deletion_protection(false)
+            .build();
+
+        assert!(matches!(
+            check_deletion_protection(&protected, false),
+            Err(Error::DeletionProtected(instance)) if instance == "protected-postgres"
+        ));
+        assert!(check_deletion_protection(&protected, true).unwrap());
+        assert!(!check_deletion_protection(&unprotected, false).unwrap());
+    }
+
+    #[test]
+    fn instance_creation_failed() {
+        let error = creation_failed(Error::InstanceFailed {
+            instance: "project-postgres".to_string(),
+            status: "incompatible-network".to_string(),
+        });
+
+        assert!(matches!(
+            error,
+            Error::InstanceCreationFailed { instance, status }
+                if instance == "project-postgres" && status == "incompatible-network"
+        ));
+        assert!(matches!(
+            creation_failed(Error::RedisNotConfigured),
+            Error::RedisNotConfigured
+        ));
+    }
+
+    #[test]
+    fn resize() {
+        let instance = DbInstance::builder()
+            .db_instance_identifier("resize-postgres")
+            .db_instance_class("db.t4g.micro")
+            .build();
+        let medium: InstanceClass = "db.t4g.medium".parse().unwrap();
+        let micro: InstanceClass = "db.t4g.micro".parse().unwrap();
+        let mut config = RdsConfig {
+            instance_class: "db.t4g.medium".to_string(),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            check_resize(&instance, &config, &medium),
+            Err(Error::ResizeNotAllowed { .. })
+        ));
+
+        config.allow_resize = true;
+        assert_eq!(
+            check_resize(&instance, &config, &medium).unwrap(),
+            Some("db.t4g.medium".to_string())
+        );
+        assert_eq!(check_resize(&instance, &config, &micro).unwrap(), None);
+        assert_eq!(
+            check_resize(&instance, &RdsConfig::default(), &medium).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn performance_insights() {
+        let config = |retention: Option<i32>| RdsConfig {
+            enable_performance_insights: Some(true),
+            performance_insights_retention_period: retention,
+            ..Default::default()
+        };
+
+        assert!(check_performance_insights(&RdsConfig::default()).is_ok());
+        assert!(check_performance_insights(&config(None)).is_ok());
+        assert!(check_performance_insights(&config(Some(93))).is_ok());
+        assert!(check_performance_insights(&config(Some(731))).is_ok());
+
+        for retention in [Some(30), Some(744)] {
+            assert!(
+                matches!(
+                    check_performance_insights(&config(retention)),
+                    Err(Error::InvalidPerformanceInsights(_))
+                ),
+                "{retention:?} was ok"
+            );
+        }
+    }
+
+    #[test]
+    fn capabilities() {
+        let everything = RdsConfig {
+            enable_performance_insights: Some(true),
+            multi_az: Some(true),
+            enable_iam_auth: Some(true),
+            storage_type: Some("io1".to_string()),
+            ..Default::default()
+        };
+
+        assert!(check_capabilities(&RdsConfig::default(), "db.t4g.micro").is_ok());
+        assert!(check_capabilities(&everything, "db.t4g.medium").is_ok());
+        assert!(check_capabilities(&everything, "db.serverless").is_ok());
+
+        // Every option the class cannot do is listed, not just the first one
+        match check_capabilities(&everything, "db.t4g.micro") {
+            Err(Error::UnsupportedOptions { class, options }) => {
+                assert_eq!(class, "db.t4g.micro");
+                for option in [
+                    "Performance Insights",
+                    "multi-AZ",
+                    "IAM authentication",
+                    "io1 storage",
+                ] {
+                    assert!(options.contains(option), "{option} is not in {options}");
+                }
+            }
+            result => panic!("unexpected result {result:?}"),
+        }
+
+        match check_capabilities(&everything, "db.t4g.small") {
+            Err(Error::UnsupportedOptions { options, .. }) => {
+                assert!(options.starts_with("Performance Insights"), "{options}");
+                assert!(!options.contains("multi-AZ"), "{options}");
+            }
+            result => panic!("unexpected result {result:?}"),
+        }
+    }
+
+    #[test]
+    fn parameter_groups() {
+        let config = |name: &str| RdsConfig {
+            db_parameter_group_name: Some(name.to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(parameter_group(&RdsConfig::default()).unwrap(), None);
+        assert_eq!(
+            parameter_group(&config("custom-postgres14")).unwrap(),
+            Some("custom-postgres14".to_string())
+        );
+
+        for name in ["", "1group", "trailing-", "double--dash", "under_score"] {
+            assert!(
+                matches!(
+                    parameter_group(&config(name)),
+                    Err(Error::InvalidParameterGroup(_))
+                ),
+                "{name:?} was ok"
+            );
+        }
+    }
+
+    #[test]
+    fn cloudwatch_logs() {
+        let config = |logs: &[&str]| RdsConfig {
+            cloudwatch_logs_exports: logs.iter().map(|log| log.to_string()).collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            cloudwatch_logs_exports(&aws_rds::Engine::Postgres(config(&[]))).unwrap(),
+            None
+        );
+        assert_eq!(
+            cloudwatch_logs_exports(&aws_rds::Engine::Mysql(config(&["error", "slowquery"])))
+                .unwrap(),
+            Some(vec!["error".to_string(), "slowquery".to_string()])
+        );
+        assert!(matches!(
+            cloudwatch_logs_exports(&aws_rds::Engine::Postgres(config(&["slowquery"]))),
+            Err(Error::InvalidLogExport { .. })
+        ));
+        assert!(matches!(
+            cloudwatch_logs_exports(&aws_rds::Engine::Mariadb(config(&["postgresql"]))),
+            Err(Error::InvalidLogExport { .. })
+        ));
+    }
+
+    #[test]
+    fn windows() {
+        assert!(is_window("sun:23:00-mon:01:30", true));
+        assert!(is_window("wed:00:00-wed:00:30", true));
+        assert!(is_window("03:00-04:00", false));
+        assert!(is_window("23:30-00:15", false));
+
+        assert!(!is_window("03:00-04:00", true));
+        assert!(!is_window("sun:23:00-mon:01:30", false));
+        assert!(!is_window("sunday:23:00-mon:01:30", true));
+        assert!(!is_window("24:00-01:00", false));
+        assert!(!is_window("03:60-04:00", false));
+        assert!(!is_window("3:00-4:00", false));
+        assert!(!is_window("03:00", false));
+        assert!(!is_window("+3:00-04:00", false));
+    }
+
+    #[test]
+    fn grpc_timeouts() {
+        assert_eq!(parse_grpc_timeout("30S"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_grpc_timeout("2H"), Some(Duration::from_secs(7200)));
+        assert_eq!(
+            parse_grpc_timeout("1500m"),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            parse_grpc_timeout("99999999n"),
+            Some(Duration::from_nanos(99_999_999))
+        );
+
+        assert_eq!(parse_grpc_timeout("S"), None);
+        assert_eq!(parse_grpc_timeout("30"), None);
+        assert_eq!(parse_grpc_timeout("30s"), None);
+        assert_eq!(parse_grpc_timeout("123456789S"), None);
+    }
+
+    #[test]
+    fn engine_specs() {
+        let config = RdsConfig::default();
+        let cases = [
+            (aws_rds::Engine::Postgres(config.clone()), "postgres", 5432),
+            (aws_rds::Engine::Mysql(config.clone()), "mysql", 3306),
+            (aws_rds::Engine::Mariadb(config), "mariadb", 3306),
+        ];
+
+        for (engine, name, port) in cases {
+            let spec = engine_spec(&engine);
+
+            assert_eq!(spec.engine_str, name);
+            assert_eq!(spec.engine_str, engine.to_string());
+            assert_eq!(spec.default_db_name, name);
+            assert_eq!(spec.port, port);
+        }
+    }
+
+    #[test]
+    fn quota() {
+        let owned = vec!["db-quota".to_string(), "other".to_string()];
+
+        assert!(check_quota("quota", "shared database", &owned, "db-quota", 2).is_ok());
+        assert!(check_quota("quota", "shared database", &owned, "third", 3).is_ok());
+        assert!(matches!(
+            check_quota("quota", "shared database", &owned, "third", 2),
+            Err(Error::QuotaExceeded { limit: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn project_tag() {
+        let instance = DbInstance::builder()
+            .db_instance_identifier("tagged-postgres")
+            .tag_list(Tag::builder().key(PROJECT_TAG).value("tagged").build())
+            .build();
+
+        assert!(is_tagged_for(&instance, "tagged"));
+        assert!(!is_tagged_for(&instance, "other"));
+        assert!(!is_tagged_for(&DbInstance::builder().build(), "tagged"));
+    }
+
+    #[tokio::test]
+    async fn instance_locks_are_forgotten() {
+        let locks = InstanceLocks::default();
+
+        let held = InstanceLock::acquire(&locks, "locked-postgres").await;
+        let other = InstanceLock::acquire(&locks, "other-postgres").await;
+        drop(other);
+        assert_eq!(locks.lock().unwrap().len(), 1);
+
+        // The waiter keeps the lock around after it is released, until the waiter is done too
+        let waiting = tokio::spawn({
+            let locks = locks.clone();
+            async move {
+                InstanceLock::acquire(&locks, "locked-postgres").await;
+            }
+        });
+        tokio::task::yield_now().await;
+        drop(held);
+
+        waiting.await.unwrap();
+        assert!(locks.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn multi_az_subnets() {
+        assert!(matches!(
+            check_multi_az_subnets(&subnet_group(&["eu-west-2a", "eu-west-2a"])),
+            Err(Error::InsufficientAvailabilityZones { zones: 1, .. })
+        ));
+        assert!(check_multi_az_subnets(&subnet_group(&["eu-west-2a", "eu-west-2b"])).is_ok());
     }
 }
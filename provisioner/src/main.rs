@@ -1,31 +1,149 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 use clap::Parser;
-use shuttle_provisioner::{Args, MyProvisioner, ProvisionerServer};
-use tonic::transport::Server;
+use shuttle_provisioner::{serve_admin, Args, Config, LogFormat, MyProvisioner, ServerBuilder};
+use tracing::error;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt::init();
-
     let Args {
+        log_format,
         ip,
         port,
+        max_concurrent_streams,
+        max_message_size,
+        request_timeout,
+        reflection,
+        admin_port,
+        admin_token,
+        shared_pg_ready_timeout,
+        provision_parallelism,
         shared_pg_uri,
+        shared_pg_min_connections,
+        shared_pg_max_connections,
+        shared_pg_connect_timeout,
+        shared_pg_idle_timeout,
+        shared_pg_max_lifetime,
+        shared_pg_statement_timeout,
+        shared_pg_lock_timeout,
+        shared_pg_least_privilege,
+        role_prefix,
+        db_prefix,
+        shared_pg_sslmode,
+        shared_pg_connection_limit,
+        shared_pg_password_secrets,
+        shared_pg_quota,
+        shared_redis_uri,
+        shared_redis_internal_address,
+        shared_redis_public_address,
+        aws_endpoint_url,
+        webhook_url,
         fqdn,
         internal_address,
+        rds_default_instance_class,
+        rds_allocated_storage,
+        rds_min_instance_class,
+        rds_single_az_fallback,
+        rds_subnet_group,
+        rds_ca_certificate,
+        rds_public_host,
+        rds_security_group_ids,
+        rds_environment,
+        rds_storage_full_increase,
+        rds_quota,
+        rds_disable_deletion_protection,
+        rds_password_secrets,
+        rds_poll_initial_interval,
+        rds_poll_max_interval,
+        rds_wait_timeout,
+        aws_call_timeout,
+        aws_attempt_timeout,
     } = Args::parse();
+
+    match log_format {
+        LogFormat::Text => tracing_subscriber::fmt::init(),
+        LogFormat::Json => tracing_subscriber::fmt().json().init(),
+    }
+
     let addr = SocketAddr::new(ip, port);
+    let config = Config {
+        default_instance_class: rds_default_instance_class,
+        allocated_storage: rds_allocated_storage,
+        min_instance_classes: rds_min_instance_class.into_iter().collect(),
+        min_connections: shared_pg_min_connections,
+        max_connections: shared_pg_max_connections,
+        connect_timeout: Duration::from_secs(shared_pg_connect_timeout),
+        idle_timeout: Duration::from_secs(shared_pg_idle_timeout),
+        max_lifetime: Duration::from_secs(shared_pg_max_lifetime),
+        statement_timeout: Duration::from_secs(shared_pg_statement_timeout),
+        lock_timeout: Duration::from_secs(shared_pg_lock_timeout),
+        least_privilege: shared_pg_least_privilege,
+        sslmode: shared_pg_sslmode,
+        role_prefix,
+        db_prefix,
+        connection_limit: shared_pg_connection_limit,
+        shared_password_secrets: shared_pg_password_secrets,
+        provision_parallelism,
+        single_az_fallback: rds_single_az_fallback,
+        storage_full_increase: rds_storage_full_increase,
+        subnet_group: Some(rds_subnet_group).filter(|group| !group.is_empty()),
+        ca_certificate: Some(rds_ca_certificate).filter(|ca| !ca.is_empty()),
+        rds_public_host,
+        security_group_ids: rds_security_group_ids,
+        environments: rds_environment.into_iter().collect(),
+        disable_deletion_protection: rds_disable_deletion_protection,
+        password_secrets: rds_password_secrets,
+        rds_poll_initial_interval: Duration::from_millis(rds_poll_initial_interval),
+        rds_poll_max_interval: Duration::from_millis(rds_poll_max_interval),
+        rds_wait_timeout: Duration::from_secs(rds_wait_timeout),
+        aws_call_timeout: Duration::from_secs(aws_call_timeout),
+        aws_attempt_timeout: Duration::from_secs(aws_attempt_timeout),
+        shared_db_quota: shared_pg_quota,
+        rds_quota,
+        shared_redis_uri,
+        shared_redis_internal_address,
+        shared_redis_public_address,
+        aws_endpoint_url,
+        webhook_url,
+    };
+
+    let provisioner =
+        MyProvisioner::with_config(&shared_pg_uri, fqdn.to_string(), internal_address, config)
+            .await?;
 
-    let provisioner = MyProvisioner::new(&shared_pg_uri, fqdn.to_string(), internal_address)
-        .await
-        .unwrap();
+    if let Some(timeout) = shared_pg_ready_timeout {
+        println!("waiting for shared Postgres to be reachable");
+        provisioner
+            .wait_for_shared_pg(Duration::from_secs(timeout))
+            .await?;
+    }
 
     println!("starting provisioner on {}", addr);
-    Server::builder()
-        .add_service(ProvisionerServer::new(provisioner))
-        .serve(addr)
-        .await?;
+    let mut server = ServerBuilder::new()
+        .max_concurrent_streams(max_concurrent_streams)
+        .max_decoding_message_size(max_message_size)
+        .reflection(reflection);
+    if let Some(timeout) = request_timeout {
+        server = server.timeout(Duration::from_secs(timeout));
+    }
+
+    let provisioner = Arc::new(provisioner);
+    if let (Some(admin_port), Some(admin_token)) = (admin_port, admin_token) {
+        let admin_addr = SocketAddr::new(ip, admin_port);
+        println!("serving operator endpoints on {}", admin_addr);
+
+        let provisioner = provisioner.clone();
+        tokio::spawn(async move {
+            if let Err(error) = serve_admin(provisioner, admin_addr, admin_token).await {
+                error!(
+                    error = &error as &dyn std::error::Error,
+                    "operator endpoints stopped"
+                );
+            }
+        });
+    }
+
+    server.serve(provisioner, addr).await?;
 
     Ok(())
 }
@@ -0,0 +1,776 @@
+use aws_sdk_rds::{
+    error::{
+        CreateDBClusterError, CreateDBClusterErrorKind, CreateDBInstanceError,
+        CreateDBInstanceErrorKind, CreateDBInstanceReadReplicaError, DeleteDBClusterError,
+        DeleteDBClusterErrorKind, DeleteDBInstanceError, DeleteDBInstanceErrorKind,
+        DescribeDBClustersError, DescribeDBClustersErrorKind, DescribeDBInstancesError,
+        DescribeDBInstancesErrorKind, DescribeDBSnapshotsError, DescribeDBSnapshotsErrorKind,
+        DescribeDBSubnetGroupsError, DescribeDBSubnetGroupsErrorKind, ModifyDBClusterError,
+        ModifyDBClusterErrorKind, ModifyDBInstanceError, ModifyDBInstanceErrorKind,
+        RestoreDBInstanceFromDBSnapshotError,
+    },
+    model::{DbCluster, DbInstance, DbSnapshot, DbSubnetGroup, Tag},
+    types::SdkError,
+    Client,
+};
+use aws_smithy_types::retry::{ErrorKind, ProvideErrorKind};
+use thiserror::Error;
+
+/// The RDS calls the provisioner makes. This is implemented for the AWS client, and lets tests
+/// swap in a fake which does not need network access
+#[tonic::async_trait]
+pub trait RdsClient: Send + Sync {
+    /// Get a single instance
+    async fn describe_instance(&self, identifier: &str) -> Result<DbInstance, RdsError>;
+
+    /// Get a page of all instances, starting at `marker`. Returns the marker of the next page
+    /// when there is one
+    async fn describe_instances(
+        &self,
+        marker: Option<String>,
+    ) -> Result<(Vec<DbInstance>, Option<String>), RdsError>;
+
+    async fn create_instance(&self, input: CreateInstance) -> Result<DbInstance, RdsError>;
+
+    /// Create a read replica of an existing instance
+    async fn create_read_replica(&self, input: CreateReadReplica) -> Result<DbInstance, RdsError>;
+
+    /// Create an instance from a snapshot
+    async fn restore_instance(&self, input: RestoreInstance) -> Result<DbInstance, RdsError>;
+
+    async fn modify_instance(&self, input: ModifyInstance) -> Result<DbInstance, RdsError>;
+
+    async fn delete_instance(&self, input: DeleteInstance) -> Result<(), RdsError>;
+
+    /// Get a subnet group, if it exists
+    async fn describe_subnet_group(&self, name: &str) -> Result<Option<DbSubnetGroup>, RdsError>;
+
+    /// Get a DB snapshot, if it exists
+    async fn describe_snapshot(&self, identifier: &str) -> Result<Option<DbSnapshot>, RdsError>;
+
+    /// Get a single Aurora cluster
+    async fn describe_cluster(&self, identifier: &str) -> Result<DbCluster, RdsError>;
+
+    /// Create an Aurora cluster. It has no instances until they are created in it
+    async fn create_cluster(&self, input: CreateCluster) -> Result<DbCluster, RdsError>;
+
+    async fn modify_cluster(&self, input: ModifyCluster) -> Result<DbCluster, RdsError>;
+
+    /// Delete an Aurora cluster. Its instances have to be deleted first
+    async fn delete_cluster(&self, input: DeleteCluster) -> Result<(), RdsError>;
+
+    /// Create an instance in an Aurora cluster
+    async fn create_cluster_instance(
+        &self,
+        input: CreateClusterInstance,
+    ) -> Result<DbInstance, RdsError>;
+}
+
+/// Settings for a new instance
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CreateInstance {
+    pub identifier: String,
+    pub master_username: String,
+    pub master_password: String,
+    pub engine: String,
+    pub instance_class: String,
+    pub allocated_storage: i32,
+    pub storage_type: Option<String>,
+    pub iops: Option<i32>,
+    pub backup_retention_period: i32,
+    pub publicly_accessible: bool,
+    pub multi_az: bool,
+    pub availability_zone: Option<String>,
+    pub deletion_protection: bool,
+    pub iam_auth: bool,
+    pub performance_insights: bool,
+    pub performance_insights_retention: Option<i32>,
+    pub db_name: String,
+    pub parameter_group: Option<String>,
+    pub subnet_group: Option<String>,
+    pub security_group_ids: Option<Vec<String>>,
+    pub ca_certificate: Option<String>,
+    pub cloudwatch_logs_exports: Option<Vec<String>>,
+    pub preferred_maintenance_window: Option<String>,
+    pub preferred_backup_window: Option<String>,
+    pub tags: Vec<Tag>,
+}
+
+/// Settings for a read replica of `source`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CreateReadReplica {
+    pub identifier: String,
+    pub source: String,
+    pub instance_class: String,
+    pub publicly_accessible: bool,
+    pub tags: Vec<Tag>,
+}
+
+/// Settings for an instance restored from a snapshot. The master username and password come
+/// from the snapshot
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RestoreInstance {
+    pub identifier: String,
+    pub snapshot: String,
+    pub instance_class: String,
+    pub publicly_accessible: bool,
+    pub multi_az: bool,
+    pub availability_zone: Option<String>,
+    pub deletion_protection: bool,
+    pub iam_auth: bool,
+    pub parameter_group: Option<String>,
+    pub subnet_group: Option<String>,
+    pub security_group_ids: Option<Vec<String>>,
+    pub cloudwatch_logs_exports: Option<Vec<String>>,
+    pub tags: Vec<Tag>,
+}
+
+/// Changes to an existing instance. Settings which are `None` are left as they are
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModifyInstance {
+    pub identifier: String,
+    pub instance_class: Option<String>,
+    pub allocated_storage: Option<i32>,
+    pub master_password: Option<String>,
+    pub multi_az: Option<bool>,
+    pub deletion_protection: Option<bool>,
+    pub security_group_ids: Option<Vec<String>>,
+    pub publicly_accessible: Option<bool>,
+    pub iam_auth: Option<bool>,
+    pub performance_insights: Option<bool>,
+    pub performance_insights_retention: Option<i32>,
+    pub parameter_group: Option<String>,
+    pub ca_certificate: Option<String>,
+    pub preferred_maintenance_window: Option<String>,
+    pub preferred_backup_window: Option<String>,
+    pub apply_immediately: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeleteInstance {
+    pub identifier: String,
+
+    /// Snapshot to take before deleting. No snapshot is taken when this is `None`
+    pub final_snapshot: Option<String>,
+}
+
+/// Settings for a new Aurora cluster. Storage is managed by Aurora, and the instance class is
+/// set on the instances of the cluster
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CreateCluster {
+    pub identifier: String,
+    pub master_username: String,
+    pub master_password: String,
+    pub engine: String,
+    pub database_name: String,
+    pub deletion_protection: bool,
+    pub iam_auth: bool,
+    pub subnet_group: Option<String>,
+    pub security_group_ids: Option<Vec<String>>,
+    pub tags: Vec<Tag>,
+}
+
+/// Changes to an existing Aurora cluster. Settings which are `None` are left as they are
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModifyCluster {
+    pub identifier: String,
+    pub master_password: Option<String>,
+    pub deletion_protection: Option<bool>,
+    pub apply_immediately: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeleteCluster {
+    pub identifier: String,
+
+    /// Snapshot to take before deleting. No snapshot is taken when this is `None`
+    pub final_snapshot: Option<String>,
+}
+
+/// Settings for an instance of the Aurora cluster `cluster`, which it gets its engine settings
+/// and credentials from
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CreateClusterInstance {
+    pub identifier: String,
+    pub cluster: String,
+    pub engine: String,
+    pub instance_class: String,
+    pub publicly_accessible: bool,
+    pub tags: Vec<Tag>,
+}
+
+#[derive(Error, Debug)]
+pub enum RdsError {
+    #[error("RDS instance does not exist")]
+    InstanceNotFound,
+
+    #[error("Aurora cluster does not exist")]
+    ClusterNotFound,
+
+    #[error("RDS instance already exists")]
+    InstanceAlreadyExists,
+
+    #[error("failed to create RDS instance")]
+    Create(#[source] SdkError<CreateDBInstanceError>),
+
+    #[error("failed to create read replica of RDS instance")]
+    CreateReadReplica(#[source] SdkError<CreateDBInstanceReadReplicaError>),
+
+    #[error("failed to restore RDS instance from snapshot")]
+    Restore(#[source] SdkError<RestoreDBInstanceFromDBSnapshotError>),
+
+    #[error("failed to get description of RDS instance")]
+    Describe(#[source] SdkError<DescribeDBInstancesError>),
+
+    #[error("failed to modify RDS instance")]
+    Modify(#[source] SdkError<ModifyDBInstanceError>),
+
+    #[error("failed to delete RDS instance")]
+    Delete(#[source] SdkError<DeleteDBInstanceError>),
+
+    #[error("failed to get description of subnet group")]
+    DescribeSubnetGroup(#[source] SdkError<DescribeDBSubnetGroupsError>),
+
+    #[error("failed to get description of DB snapshot")]
+    DescribeSnapshot(#[source] SdkError<DescribeDBSnapshotsError>),
+
+    #[error("failed to create Aurora cluster")]
+    CreateCluster(#[source] SdkError<CreateDBClusterError>),
+
+    #[error("failed to get description of Aurora cluster")]
+    DescribeCluster(#[source] SdkError<DescribeDBClustersError>),
+
+    #[error("failed to modify Aurora cluster")]
+    ModifyCluster(#[source] SdkError<ModifyDBClusterError>),
+
+    #[error("failed to delete Aurora cluster")]
+    DeleteCluster(#[source] SdkError<DeleteDBClusterError>),
+}
+
+impl RdsError {
+    /// Whether the call might succeed when it is made again, like after being throttled or
+    /// timing out. Validation errors will keep failing, so they are not retryable
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            RdsError::InstanceNotFound
+            | RdsError::InstanceAlreadyExists
+            | RdsError::ClusterNotFound => false,
+            RdsError::Create(error) => is_retryable(error),
+            RdsError::CreateReadReplica(error) => is_retryable(error),
+            RdsError::Restore(error) => is_retryable(error),
+            RdsError::Describe(error) => is_retryable(error),
+            RdsError::Modify(error) => is_retryable(error),
+            RdsError::Delete(error) => is_retryable(error),
+            RdsError::DescribeSubnetGroup(error) => is_retryable(error),
+            RdsError::DescribeSnapshot(error) => is_retryable(error),
+            RdsError::CreateCluster(error) => is_retryable(error),
+            RdsError::DescribeCluster(error) => is_retryable(error),
+            RdsError::ModifyCluster(error) => is_retryable(error),
+            RdsError::DeleteCluster(error) => is_retryable(error),
+        }
+    }
+
+    /// ID AWS gave the failed request, which AWS support asks for when looking into a failure
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            RdsError::InstanceNotFound
+            | RdsError::InstanceAlreadyExists
+            | RdsError::ClusterNotFound => None,
+            RdsError::Create(error) => service_error(error)?.request_id(),
+            RdsError::CreateReadReplica(error) => service_error(error)?.request_id(),
+            RdsError::Restore(error) => service_error(error)?.request_id(),
+            RdsError::Describe(error) => service_error(error)?.request_id(),
+            RdsError::Modify(error) => service_error(error)?.request_id(),
+            RdsError::Delete(error) => service_error(error)?.request_id(),
+            RdsError::DescribeSubnetGroup(error) => service_error(error)?.request_id(),
+            RdsError::DescribeSnapshot(error) => service_error(error)?.request_id(),
+            RdsError::CreateCluster(error) => service_error(error)?.request_id(),
+            RdsError::DescribeCluster(error) => service_error(error)?.request_id(),
+            RdsError::ModifyCluster(error) => service_error(error)?.request_id(),
+            RdsError::DeleteCluster(error) => service_error(error)?.request_id(),
+        }
+    }
+}
+
+/// The error AWS responded with, if the request got that far
+fn service_error<E>(error: &SdkError<E>) -> Option<&E> {
+    match error {
+        SdkError::ServiceError { err, .. } => Some(err),
+        _ => None,
+    }
+}
+
+pub(crate) fn is_retryable<E: ProvideErrorKind>(error: &SdkError<E>) -> bool {
+    match error {
+        SdkError::TimeoutError(_)
+        | SdkError::DispatchFailure(_)
+        | SdkError::ResponseError { .. } => true,
+        SdkError::ServiceError { err, .. } => {
+            matches!(
+                err.retryable_error_kind(),
+                Some(ErrorKind::ThrottlingError | ErrorKind::TransientError)
+            ) || matches!(
+                err.code(),
+                Some("Throttling" | "ThrottlingException" | "RequestLimitExceeded")
+            )
+        }
+        SdkError::ConstructionFailure(_) => false,
+    }
+}
+
+#[tonic::async_trait]
+impl RdsClient for Client {
+    async fn describe_instance(&self, identifier: &str) -> Result<DbInstance, RdsError> {
+        let output = self
+            .describe_db_instances()
+            .db_instance_identifier(identifier)
+            .send()
+            .await;
+
+        match output {
+            Ok(output) => output
+                .db_instances
+                .unwrap_or_default()
+                .into_iter()
+                .next()
+                .ok_or(RdsError::InstanceNotFound),
+            Err(SdkError::ServiceError { err, .. })
+                if matches!(
+                    err.kind,
+                    DescribeDBInstancesErrorKind::DbInstanceNotFoundFault(_)
+                ) =>
+            {
+                Err(RdsError::InstanceNotFound)
+            }
+            Err(error) => Err(RdsError::Describe(error)),
+        }
+    }
+
+    async fn describe_instances(
+        &self,
+        marker: Option<String>,
+    ) -> Result<(Vec<DbInstance>, Option<String>), RdsError> {
+        let output = self
+            .describe_db_instances()
+            .set_marker(marker)
+            .send()
+            .await
+            .map_err(RdsError::Describe)?;
+
+        Ok((output.db_instances.unwrap_or_default(), output.marker))
+    }
+
+    async fn create_instance(&self, input: CreateInstance) -> Result<DbInstance, RdsError> {
+        let output = self
+            .create_db_instance()
+            .db_instance_identifier(input.identifier)
+            .master_username(input.master_username)
+            .master_user_password(input.master_password)
+            .engine(input.engine)
+            .db_instance_class(input.instance_class)
+            .allocated_storage(input.allocated_storage)
+            .set_storage_type(input.storage_type)
+            .set_iops(input.iops)
+            .backup_retention_period(input.backup_retention_period)
+            .publicly_accessible(input.publicly_accessible)
+            .multi_az(input.multi_az)
+            .set_availability_zone(input.availability_zone)
+            .deletion_protection(input.deletion_protection)
+            .enable_iam_database_authentication(input.iam_auth)
+            .enable_performance_insights(input.performance_insights)
+            .set_performance_insights_retention_period(input.performance_insights_retention)
+            .db_name(input.db_name)
+            .set_db_parameter_group_name(input.parameter_group)
+            .set_db_subnet_group_name(input.subnet_group)
+            .set_vpc_security_group_ids(input.security_group_ids)
+            .set_ca_certificate_identifier(input.ca_certificate)
+            .set_enable_cloudwatch_logs_exports(input.cloudwatch_logs_exports)
+            .set_preferred_maintenance_window(input.preferred_maintenance_window)
+            .set_preferred_backup_window(input.preferred_backup_window)
+            .set_tags(Some(input.tags))
+            .send()
+            .await;
+
+        match output {
+            Ok(output) => Ok(output.db_instance.expect("to be able to create instance")),
+            Err(SdkError::ServiceError { err, .. })
+                if matches!(
+                    err.kind,
+                    CreateDBInstanceErrorKind::DbInstanceAlreadyExistsFault(_)
+                ) =>
+            {
+                Err(RdsError::InstanceAlreadyExists)
+            }
+            Err(error) => Err(RdsError::Create(error)),
+        }
+    }
+
+    async fn create_read_replica(&self, input: CreateReadReplica) -> Result<DbInstance, RdsError> {
+        let output = self
+            .create_db_instance_read_replica()
+            .db_instance_identifier(input.identifier)
+            .source_db_instance_identifier(input.source)
+            .db_instance_class(input.instance_class)
+            .publicly_accessible(input.publicly_accessible)
+            .set_tags(Some(input.tags))
+            .send()
+            .await
+            .map_err(RdsError::CreateReadReplica)?;
+
+        Ok(output
+            .db_instance
+            .expect("to be able to create read replica"))
+    }
+
+    async fn restore_instance(&self, input: RestoreInstance) -> Result<DbInstance, RdsError> {
+        let output = self
+            .restore_db_instance_from_db_snapshot()
+            .db_instance_identifier(input.identifier)
+            .db_snapshot_identifier(input.snapshot)
+            .db_instance_class(input.instance_class)
+            .publicly_accessible(input.publicly_accessible)
+            .multi_az(input.multi_az)
+            .set_availability_zone(input.availability_zone)
+            .deletion_protection(input.deletion_protection)
+            .enable_iam_database_authentication(input.iam_auth)
+            .set_db_parameter_group_name(input.parameter_group)
+            .set_db_subnet_group_name(input.subnet_group)
+            .set_vpc_security_group_ids(input.security_group_ids)
+            .set_enable_cloudwatch_logs_exports(input.cloudwatch_logs_exports)
+            .set_tags(Some(input.tags))
+            .send()
+            .await
+            .map_err(RdsError::Restore)?;
+
+        Ok(output.db_instance.expect("to be able to restore instance"))
+    }
+
+    async fn modify_instance(&self, input: ModifyInstance) -> Result<DbInstance, RdsError> {
+        let output = self
+            .modify_db_instance()
+            .db_instance_identifier(input.identifier)
+            .set_db_instance_class(input.instance_class)
+            .set_allocated_storage(input.allocated_storage)
+            .set_master_user_password(input.master_password)
+            .set_multi_az(input.multi_az)
+            .set_deletion_protection(input.deletion_protection)
+            .set_vpc_security_group_ids(input.security_group_ids)
+            .set_publicly_accessible(input.publicly_accessible)
+            .set_enable_iam_database_authentication(input.iam_auth)
+            .set_enable_performance_insights(input.performance_insights)
+            .set_performance_insights_retention_period(input.performance_insights_retention)
+            .set_db_parameter_group_name(input.parameter_group)
+            .set_ca_certificate_identifier(input.ca_certificate)
+            .set_preferred_maintenance_window(input.preferred_maintenance_window)
+            .set_preferred_backup_window(input.preferred_backup_window)
+            .set_apply_immediately(input.apply_immediately)
+            .send()
+            .await;
+
+        match output {
+            Ok(output) => Ok(output.db_instance.expect("to be able to modify instance")),
+            Err(SdkError::ServiceError { err, .. })
+                if matches!(
+                    err.kind,
+                    ModifyDBInstanceErrorKind::DbInstanceNotFoundFault(_)
+                ) =>
+            {
+                Err(RdsError::InstanceNotFound)
+            }
+            Err(error) => Err(RdsError::Modify(error)),
+        }
+    }
+
+    async fn delete_instance(&self, input: DeleteInstance) -> Result<(), RdsError> {
+        let output = self
+            .delete_db_instance()
+            .db_instance_identifier(input.identifier)
+            .skip_final_snapshot(input.final_snapshot.is_none())
+            .set_final_db_snapshot_identifier(input.final_snapshot)
+            .send()
+            .await;
+
+        match output {
+            Ok(_) => Ok(()),
+            Err(SdkError::ServiceError { err, .. })
+                if matches!(
+                    err.kind,
+                    DeleteDBInstanceErrorKind::DbInstanceNotFoundFault(_)
+                ) =>
+            {
+                Err(RdsError::InstanceNotFound)
+            }
+            Err(error) => Err(RdsError::Delete(error)),
+        }
+    }
+
+    async fn describe_subnet_group(&self, name: &str) -> Result<Option<DbSubnetGroup>, RdsError> {
+        let output = self
+            .describe_db_subnet_groups()
+            .db_subnet_group_name(name)
+            .send()
+            .await;
+
+        match output {
+            Ok(output) => Ok(output
+                .db_subnet_groups
+                .unwrap_or_default()
+                .into_iter()
+                .next()),
+            Err(SdkError::ServiceError { err, .. })
+                if matches!(
+                    err.kind,
+                    DescribeDBSubnetGroupsErrorKind::DbSubnetGroupNotFoundFault(_)
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(error) => Err(RdsError::DescribeSubnetGroup(error)),
+        }
+    }
+
+    async fn describe_snapshot(&self, identifier: &str) -> Result<Option<DbSnapshot>, RdsError> {
+        let output = self
+            .describe_db_snapshots()
+            .db_snapshot_identifier(identifier)
+            .send()
+            .await;
+
+        match output {
+            Ok(output) => Ok(output.db_snapshots.unwrap_or_default().into_iter().next()),
+            Err(SdkError::ServiceError { err, .. })
+                if matches!(
+                    err.kind,
+                    DescribeDBSnapshotsErrorKind::DbSnapshotNotFoundFault(_)
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(error) => Err(RdsError::DescribeSnapshot(error)),
+        }
+    }
+
+    async fn describe_cluster(&self, identifier: &str) -> Result<DbCluster, RdsError> {
+        let output = self
+            .describe_db_clusters()
+            .db_cluster_identifier(identifier)
+            .send()
+            .await;
+
+        match output {
+            Ok(output) => output
+                .db_clusters
+                .unwrap_or_default()
+                .into_iter()
+                .next()
+                .ok_or(RdsError::ClusterNotFound),
+            Err(SdkError::ServiceError { err, .. })
+                if matches!(
+                    err.kind,
+                    DescribeDBClustersErrorKind::DbClusterNotFoundFault(_)
+                ) =>
+            {
+                Err(RdsError::ClusterNotFound)
+            }
+            Err(error) => Err(RdsError::DescribeCluster(error)),
+        }
+    }
+
+    async fn create_cluster(&self, input: CreateCluster) -> Result<DbCluster, RdsError> {
+        let output = self
+            .create_db_cluster()
+            .db_cluster_identifier(input.identifier)
+            .master_username(input.master_username)
+            .master_user_password(input.master_password)
+            .engine(input.engine)
+            .database_name(input.database_name)
+            .deletion_protection(input.deletion_protection)
+            .enable_iam_database_authentication(input.iam_auth)
+            .set_db_subnet_group_name(input.subnet_group)
+            .set_vpc_security_group_ids(input.security_group_ids)
+            .set_tags(Some(input.tags))
+            .send()
+            .await;
+
+        match output {
+            Ok(output) => Ok(output.db_cluster.expect("to be able to create cluster")),
+            Err(SdkError::ServiceError { err, .. })
+                if matches!(
+                    err.kind,
+                    CreateDBClusterErrorKind::DbClusterAlreadyExistsFault(_)
+                ) =>
+            {
+                Err(RdsError::InstanceAlreadyExists)
+            }
+            Err(error) => Err(RdsError::CreateCluster(error)),
+        }
+    }
+
+    async fn modify_cluster(&self, input: ModifyCluster) -> Result<DbCluster, RdsError> {
+        let output = self
+            .modify_db_cluster()
+            .db_cluster_identifier(input.identifier)
+            .set_master_user_password(input.master_password)
+            .set_deletion_protection(input.deletion_protection)
+            .set_apply_immediately(input.apply_immediately)
+            .send()
+            .await;
+
+        match output {
+            Ok(output) => Ok(output.db_cluster.expect("to be able to modify cluster")),
+            Err(SdkError::ServiceError { err, .. })
+                if matches!(
+                    err.kind,
+                    ModifyDBClusterErrorKind::DbClusterNotFoundFault(_)
+                ) =>
+            {
+                Err(RdsError::ClusterNotFound)
+            }
+            Err(error) => Err(RdsError::ModifyCluster(error)),
+        }
+    }
+
+    async fn delete_cluster(&self, input: DeleteCluster) -> Result<(), RdsError> {
+        let output = self
+            .delete_db_cluster()
+            .db_cluster_identifier(input.identifier)
+            .skip_final_snapshot(input.final_snapshot.is_none())
+            .set_final_db_snapshot_identifier(input.final_snapshot)
+            .send()
+            .await;
+
+        match output {
+            Ok(_) => Ok(()),
+            Err(SdkError::ServiceError { err, .. })
+                if matches!(
+                    err.kind,
+                    DeleteDBClusterErrorKind::DbClusterNotFoundFault(_)
+                ) =>
+            {
+                Err(RdsError::ClusterNotFound)
+            }
+            Err(error) => Err(RdsError::DeleteCluster(error)),
+        }
+    }
+
+    async fn create_cluster_instance(
+        &self,
+        input: CreateClusterInstance,
+    ) -> Result<DbInstance, RdsError> {
+        let output = self
+            .create_db_instance()
+            .db_instance_identifier(input.identifier)
+            .db_cluster_identifier(input.cluster)
+            .engine(input.engine)
+            .db_instance_class(input.instance_class)
+            .publicly_accessible(input.publicly_accessible)
+            .set_tags(Some(input.tags))
+            .send()
+            .await;
+
+        match output {
+            Ok(output) => Ok(output.db_instance.expect("to be able to create instance")),
+            Err(SdkError::ServiceError { err, .. })
+                if matches!(
+                    err.kind,
+                    CreateDBInstanceErrorKind::DbInstanceAlreadyExistsFault(_)
+                ) =>
+            {
+                Err(RdsError::InstanceAlreadyExists)
+            }
+            Err(error) => Err(RdsError::Create(error)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use aws_sdk_rds::{Credentials, Region};
+    use aws_smithy_http::{body::SdkBody, result::ConnectorError};
+
+    use super::*;
+
+    const INSTANCE_NOT_FOUND: &str = r#"<ErrorResponse xmlns="http://rds.amazonaws.com/doc/2014-10-31/">
+  <Error>
+    <Type>Sender</Type>
+    <Code>DBInstanceNotFound</Code>
+    <Message>DBInstance not found</Message>
+  </Error>
+  <RequestId>test</RequestId>
+</ErrorResponse>"#;
+
+    /// AWS client which answers every call as if the instance does not exist, keeping the body of
+    /// every request it gets so tests can check what reached AWS
+    fn recording_client() -> (Client, Arc<Mutex<Vec<String>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+
+        let connector = tower::service_fn(move |request: http::Request<SdkBody>| {
+            let body =
+                String::from_utf8_lossy(request.body().bytes().unwrap_or_default()).into_owned();
+            recorded.lock().unwrap().push(body);
+
+            let response = http::Response::builder()
+                .status(404)
+                .body(SdkBody::from(INSTANCE_NOT_FOUND))
+                .unwrap();
+            async move { Ok::<_, ConnectorError>(response) }
+        });
+        let config = aws_sdk_rds::Config::builder()
+            .region(Region::new("eu-west-2"))
+            .credentials_provider(Credentials::new("key", "secret", None, None, "test"))
+            .build();
+
+        (Client::from_conf_conn(config, connector), requests)
+    }
+
+    #[tokio::test]
+    async fn create_settings_reach_aws() {
+        let (client, requests) = recording_client();
+
+        let input = CreateInstance {
+            identifier: "settings-postgres".to_string(),
+            multi_az: true,
+            iam_auth: true,
+            ..Default::default()
+        };
+        // The fake has no instance to return, but the call was sent all the same
+        client.create_instance(input).await.unwrap_err();
+
+        let body = requests.lock().unwrap()[0].clone();
+        for setting in [
+            "Action=CreateDBInstance&",
+            "MultiAZ=true",
+            "EnableIAMDatabaseAuthentication=true",
+        ] {
+            assert!(body.contains(setting), "{body}");
+        }
+    }
+
+    #[tokio::test]
+    async fn modify_settings_reach_aws() {
+        let (client, requests) = recording_client();
+
+        let input = ModifyInstance {
+            identifier: "settings-postgres".to_string(),
+            multi_az: Some(true),
+            iam_auth: Some(true),
+            ..Default::default()
+        };
+        assert!(matches!(
+            client.modify_instance(input).await,
+            Err(RdsError::InstanceNotFound)
+        ));
+
+        let body = requests.lock().unwrap()[0].clone();
+        for setting in [
+            "Action=ModifyDBInstance&",
+            "MultiAZ=true",
+            "EnableIAMDatabaseAuthentication=true",
+        ] {
+            assert!(body.contains(setting), "{body}");
+        }
+    }
+}
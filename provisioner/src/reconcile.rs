@@ -0,0 +1,268 @@
+use std::collections::{BTreeSet, HashSet};
+
+use serde::Serialize;
+use shuttle_proto::provisioner::{aws_rds, RdsConfig};
+use tracing::{info, warn};
+
+use crate::{project_tag, shared, Error, MyProvisioner};
+
+/// Everything the provisioner made for projects, going by the project tag on RDS instances and
+/// the name prefixes of shared Postgres databases and roles
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Inventory {
+    pub rds_instances: Vec<TaggedInstance>,
+    pub shared_databases: Vec<String>,
+    pub shared_roles: Vec<String>,
+}
+
+/// An RDS instance and the project it is tagged with
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TaggedInstance {
+    pub identifier: String,
+    pub project: String,
+    pub engine: String,
+}
+
+/// What a cleanup deleted, and why the rest of it could not be deleted
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Cleanup {
+    pub deleted: Inventory,
+    pub failures: Vec<String>,
+}
+
+impl Inventory {
+    /// The part of the inventory which belongs to none of `live_projects`. Shared names are
+    /// mapped back to their project by taking off `db_prefix` and `role_prefix`
+    pub fn orphans(
+        &self,
+        live_projects: &HashSet<String>,
+        db_prefix: &str,
+        role_prefix: &str,
+    ) -> Inventory {
+        let is_orphan = |name: &str, prefix: &str| {
+            name.strip_prefix(prefix)
+                .map_or(false, |project| !live_projects.contains(project))
+        };
+        // Extra roles of a project add an underscore and their suffix to its role name
+        let is_orphan_role = |name: &str| {
+            name.strip_prefix(role_prefix).map_or(false, |rest| {
+                let project = rest.split('_').next().unwrap_or(rest);
+
+                !live_projects.contains(project)
+            })
+        };
+
+        Inventory {
+            rds_instances: self
+                .rds_instances
+                .iter()
+                .filter(|instance| !live_projects.contains(&instance.project))
+                .cloned()
+                .collect(),
+            shared_databases: self
+                .shared_databases
+                .iter()
+                .filter(|name| is_orphan(name, db_prefix))
+                .cloned()
+                .collect(),
+            shared_roles: self
+                .shared_roles
+                .iter()
+                .filter(|name| is_orphan_role(name))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+impl MyProvisioner {
+    /// Everything the provisioner made, on RDS and on the shared Postgres
+    pub async fn inventory(&self) -> Result<Inventory, Error> {
+        let (shared_databases, shared_roles) = self.shared_inventory().await?;
+
+        Ok(Inventory {
+            rds_instances: self.rds_inventory().await?,
+            shared_databases,
+            shared_roles,
+        })
+    }
+
+    /// Every RDS instance with a project tag
+    pub async fn rds_inventory(&self) -> Result<Vec<TaggedInstance>, Error> {
+        let mut instances = Vec::new();
+        let mut marker = None;
+
+        loop {
+            let (page, next_marker) = self.rds_client.describe_instances(marker).await?;
+
+            instances.extend(page.iter().filter_map(|instance| {
+                Some(TaggedInstance {
+                    identifier: instance.db_instance_identifier.clone().unwrap_or_default(),
+                    project: project_tag(instance)?.to_string(),
+                    engine: instance.engine.clone().unwrap_or_default(),
+                })
+            }));
+            marker = next_marker;
+
+            if marker.is_none() {
+                break;
+            }
+        }
+
+        Ok(instances)
+    }
+
+    /// Databases and roles on the shared Postgres with the names given to projects
+    pub async fn shared_inventory(&self) -> Result<(Vec<String>, Vec<String>), Error> {
+        let databases = sqlx::query_scalar(
+            "SELECT datname FROM pg_database WHERE starts_with(datname, $1) ORDER BY datname",
+        )
+        .bind(&self.config.db_prefix)
+        .fetch_all(&self.pool)
+        .await?;
+        let roles = sqlx::query_scalar(
+            "SELECT rolname FROM pg_roles WHERE starts_with(rolname, $1) ORDER BY rolname",
+        )
+        .bind(&self.config.role_prefix)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok((databases, roles))
+    }
+
+    /// Everything which belongs to none of `live_projects`
+    pub async fn orphans(&self, live_projects: &HashSet<String>) -> Result<Inventory, Error> {
+        Ok(self.inventory().await?.orphans(
+            live_projects,
+            &self.config.db_prefix,
+            &self.config.role_prefix,
+        ))
+    }
+
+    /// Delete everything in `orphans`, carrying on past what cannot be deleted. RDS instances are
+    /// deleted like a delete request for their project would, which takes their read replica and
+    /// final snapshot into account
+    pub async fn cleanup_orphans(&self, orphans: &Inventory) -> Cleanup {
+        let mut cleanup = Cleanup::default();
+
+        // A primary and its replica share a project and engine, and deleting the primary takes
+        // care of both
+        let projects: BTreeSet<_> = orphans
+            .rds_instances
+            .iter()
+            .map(|instance| (instance.project.as_str(), instance.engine.as_str()))
+            .collect();
+        for (project, engine) in projects {
+            let result = match rds_engine_named(engine) {
+                Some(rds_engine) => self.delete_aws_rds(project, &rds_engine).await,
+                None => Err(Error::Plain(format!("cannot clean up {engine} instances"))),
+            };
+
+            match result {
+                Ok(_) => {
+                    info!(project, engine, "deleted orphaned AWS RDS");
+                    cleanup.deleted.rds_instances.extend(
+                        orphans
+                            .rds_instances
+                            .iter()
+                            .filter(|instance| {
+                                instance.project == project && instance.engine == engine
+                            })
+                            .cloned(),
+                    );
+                }
+                Err(error) => cleanup.fail(format!("AWS RDS {engine} of {project}"), error),
+            }
+        }
+
+        // Databases go first, as roles cannot be dropped while they own one
+        for database in &orphans.shared_databases {
+            match shared::drop_shared_db(&self.pool, database).await {
+                Ok(()) => cleanup.deleted.shared_databases.push(database.clone()),
+                Err(error) => cleanup.fail(format!("shared database {database}"), error),
+            }
+        }
+
+        for role in &orphans.shared_roles {
+            match shared::drop_shared_role(&self.pool, role).await {
+                Ok(()) => cleanup.deleted.shared_roles.push(role.clone()),
+                Err(error) => cleanup.fail(format!("shared role {role}"), error),
+            }
+        }
+
+        cleanup
+    }
+}
+
+impl Cleanup {
+    fn fail(&mut self, resource: String, error: Error) {
+        warn!(
+            error = &error as &dyn std::error::Error,
+            "failed to clean up orphaned {resource}"
+        );
+        self.failures.push(format!("{resource}: {error}"));
+    }
+}
+
+/// Engine of an RDS request for instances of the engine called `engine`
+fn rds_engine_named(engine: &str) -> Option<aws_rds::Engine> {
+    let config = RdsConfig::default();
+
+    match engine {
+        "postgres" => Some(aws_rds::Engine::Postgres(config)),
+        "mysql" => Some(aws_rds::Engine::Mysql(config)),
+        "mariadb" => Some(aws_rds::Engine::Mariadb(config)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(identifier: &str, project: &str) -> TaggedInstance {
+        TaggedInstance {
+            identifier: identifier.to_string(),
+            project: project.to_string(),
+            engine: "postgres".to_string(),
+        }
+    }
+
+    #[test]
+    fn orphans() {
+        let inventory = Inventory {
+            rds_instances: vec![
+                instance("live-postgres", "live"),
+                instance("gone-postgres", "gone"),
+                instance("gone-postgres-replica", "gone"),
+            ],
+            shared_databases: vec!["db-live".to_string(), "db-gone".to_string()],
+            shared_roles: vec![
+                "user-live".to_string(),
+                "user-live_app".to_string(),
+                "user-gone".to_string(),
+                "user-gone_app".to_string(),
+            ],
+        };
+        let live = HashSet::from(["live".to_string()]);
+
+        assert_eq!(
+            inventory.orphans(&live, "db-", "user-"),
+            Inventory {
+                rds_instances: vec![
+                    instance("gone-postgres", "gone"),
+                    instance("gone-postgres-replica", "gone"),
+                ],
+                shared_databases: vec!["db-gone".to_string()],
+                shared_roles: vec!["user-gone".to_string(), "user-gone_app".to_string()],
+            }
+        );
+
+        // Nothing is an orphan while its project is live
+        let live = HashSet::from(["live".to_string(), "gone".to_string()]);
+        assert_eq!(
+            inventory.orphans(&live, "db-", "user-"),
+            Inventory::default()
+        );
+    }
+}
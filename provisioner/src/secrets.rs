@@ -0,0 +1,109 @@
+use aws_sdk_secretsmanager::{
+    error::{
+        CreateSecretError, CreateSecretErrorKind, DescribeSecretError, DescribeSecretErrorKind,
+        PutSecretValueError,
+    },
+    model::Tag,
+    types::SdkError,
+    Client,
+};
+use thiserror::Error;
+
+use crate::{rds::is_retryable, PROJECT_TAG};
+
+/// The Secrets Manager calls the provisioner makes. This is implemented for the AWS client, and
+/// lets tests swap in a fake which does not need network access
+#[tonic::async_trait]
+pub trait SecretsClient: Send + Sync {
+    /// Store a value in a secret, creating the secret when it does not exist yet. A secret which
+    /// exists gets the value as its new current version. Returns the ARN of the secret
+    async fn put_secret(&self, input: PutSecret) -> Result<String, SecretsError>;
+
+    /// Get the ARN of the secret called `name`, if it exists
+    async fn secret_arn(&self, name: &str) -> Result<Option<String>, SecretsError>;
+}
+
+/// A secret value to store
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PutSecret {
+    pub name: String,
+    pub value: String,
+
+    /// Project the secret belongs to. New secrets are tagged with it
+    pub project: String,
+}
+
+#[derive(Error, Debug)]
+pub enum SecretsError {
+    #[error("failed to create secret")]
+    Create(#[source] SdkError<CreateSecretError>),
+
+    #[error("failed to update secret")]
+    Put(#[source] SdkError<PutSecretValueError>),
+
+    #[error("failed to get description of secret")]
+    Describe(#[source] SdkError<DescribeSecretError>),
+}
+
+impl SecretsError {
+    /// Whether the call might succeed when it is made again, like after being throttled or
+    /// timing out
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            SecretsError::Create(error) => is_retryable(error),
+            SecretsError::Put(error) => is_retryable(error),
+            SecretsError::Describe(error) => is_retryable(error),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl SecretsClient for Client {
+    async fn put_secret(&self, input: PutSecret) -> Result<String, SecretsError> {
+        let output = self
+            .create_secret()
+            .name(&input.name)
+            .secret_string(&input.value)
+            .tags(
+                Tag::builder()
+                    .key(PROJECT_TAG)
+                    .value(&input.project)
+                    .build(),
+            )
+            .send()
+            .await;
+
+        match output {
+            Ok(output) => Ok(output.arn.expect("secret to have an ARN")),
+            Err(SdkError::ServiceError { err, .. })
+                if matches!(err.kind, CreateSecretErrorKind::ResourceExistsException(_)) =>
+            {
+                let output = self
+                    .put_secret_value()
+                    .secret_id(&input.name)
+                    .secret_string(&input.value)
+                    .send()
+                    .await
+                    .map_err(SecretsError::Put)?;
+
+                Ok(output.arn.expect("secret to have an ARN"))
+            }
+            Err(error) => Err(SecretsError::Create(error)),
+        }
+    }
+
+    async fn secret_arn(&self, name: &str) -> Result<Option<String>, SecretsError> {
+        match self.describe_secret().secret_id(name).send().await {
+            Ok(output) => Ok(output.arn),
+            Err(SdkError::ServiceError { err, .. })
+                if matches!(
+                    err.kind,
+                    DescribeSecretErrorKind::ResourceNotFoundException(_)
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(error) => Err(SecretsError::Describe(error)),
+        }
+    }
+}
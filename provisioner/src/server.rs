@@ -0,0 +1,209 @@
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::Buf;
+use http_body::{Body, SizeHint};
+use shuttle_proto::provisioner::{provisioner_server::ProvisionerServer, FILE_DESCRIPTOR_SET};
+use tonic::{
+    body::BoxBody,
+    transport::{NamedService, Server},
+    Status,
+};
+use tower_service::Service;
+
+use crate::MyProvisioner;
+
+/// HTTP/2 peers commonly allow this many streams, and it leaves room for a few large batches
+pub const DEFAULT_MAX_CONCURRENT_STREAMS: u32 = 100;
+/// The limit gRPC clients put on messages by default. It fits batches of hundreds of requests
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Serves a [MyProvisioner] over gRPC, with limits on what clients can ask of it
+#[derive(Clone, Debug)]
+pub struct ServerBuilder {
+    max_concurrent_streams: u32,
+    max_message_size: usize,
+    timeout: Option<Duration>,
+    reflection: bool,
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        Self {
+            max_concurrent_streams: DEFAULT_MAX_CONCURRENT_STREAMS,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            timeout: None,
+            reflection: false,
+        }
+    }
+}
+
+impl ServerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Most requests a single connection can have in flight at once
+    pub fn max_concurrent_streams(self, max_concurrent_streams: u32) -> Self {
+        Self {
+            max_concurrent_streams,
+            ..self
+        }
+    }
+
+    /// Largest request message in bytes. Bigger requests fail with `ResourceExhausted` before
+    /// they are decoded
+    pub fn max_decoding_message_size(self, max_message_size: usize) -> Self {
+        Self {
+            max_message_size,
+            ..self
+        }
+    }
+
+    /// Cancel requests which take longer than `timeout`. Creating an RDS instance can take most
+    /// of an hour, so there is no timeout by default
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Serve gRPC reflection, so tools like grpcurl can find the methods of the provisioner
+    /// without its proto files
+    pub fn reflection(self, reflection: bool) -> Self {
+        Self { reflection, ..self }
+    }
+
+    /// Serve `provisioner` on `addr` until the server fails. It can be shared with other servers
+    /// by passing it in an [Arc]
+    pub async fn serve(
+        self,
+        provisioner: impl Into<Arc<MyProvisioner>>,
+        addr: SocketAddr,
+    ) -> Result<(), tonic::transport::Error> {
+        let mut server = Server::builder().max_concurrent_streams(self.max_concurrent_streams);
+        if let Some(timeout) = self.timeout {
+            server = server.timeout(timeout);
+        }
+
+        let reflection = self.reflection.then(|| {
+            tonic_reflection::server::Builder::configure()
+                .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+                .build()
+                .expect("provisioner descriptors to be valid")
+        });
+
+        server
+            .add_service(MessageSizeLimit {
+                inner: ProvisionerServer::from_arc(provisioner.into()),
+                limit: self.max_message_size,
+            })
+            .add_optional_service(reflection)
+            .serve(addr)
+            .await
+    }
+}
+
+/// Fails requests with bodies over `limit` bytes. Every call is unary, so the body is a single
+/// message behind a 5 byte frame header
+#[derive(Clone)]
+struct MessageSizeLimit<S> {
+    inner: S,
+    limit: usize,
+}
+
+impl<S: NamedService> NamedService for MessageSizeLimit<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S> Service<http::Request<hyper::Body>> for MessageSizeLimit<S>
+where
+    S: Service<
+        http::Request<LimitedBody<hyper::Body>>,
+        Response = http::Response<BoxBody>,
+        Error = Infallible,
+    >,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<hyper::Body>) -> Self::Future {
+        let limit = self.limit;
+
+        self.inner.call(request.map(|body| LimitedBody {
+            inner: body,
+            limit,
+            remaining: limit + 5,
+        }))
+    }
+}
+
+/// A body which errors once more than `limit` bytes of message come through it. The error is a
+/// [Status], which tonic hands back to the client as is
+struct LimitedBody<B> {
+    inner: B,
+    limit: usize,
+    remaining: usize,
+}
+
+impl<B> Body for LimitedBody<B>
+where
+    B: Body + Unpin,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    type Data = B::Data;
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let data = match Pin::new(&mut self.inner).poll_data(cx) {
+            Poll::Ready(Some(Ok(data))) => data,
+            Poll::Ready(Some(Err(error))) => return Poll::Ready(Some(Err(error.into()))),
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => return Poll::Pending,
+        };
+
+        match self.remaining.checked_sub(data.remaining()) {
+            Some(remaining) => {
+                self.remaining = remaining;
+
+                Poll::Ready(Some(Ok(data)))
+            }
+            None => Poll::Ready(Some(Err(Box::new(Status::resource_exhausted(format!(
+                "request is larger than the limit of {} bytes",
+                self.limit
+            )))))),
+        }
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.inner)
+            .poll_trailers(cx)
+            .map_err(Into::into)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
@@ -0,0 +1,540 @@
+use sqlx::{postgres::PgConnectOptions, ConnectOptions, Connection, PgPool};
+use tracing::info;
+
+use crate::{generate_password, Error};
+
+/// SQLSTATE of statements which write to a read-only transaction, which is all of them on a
+/// replica or a server in recovery
+const READ_ONLY_SQL_TRANSACTION: &str = "25006";
+
+/// Encoding and locale of a new database. The server defaults are used for what is not set
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DbLocale {
+    pub encoding: Option<String>,
+    pub lc_collate: Option<String>,
+    pub lc_ctype: Option<String>,
+}
+
+impl DbLocale {
+    /// Settings in the order they go into `CREATE DATABASE`
+    fn settings(&self) -> [(&'static str, Option<&str>); 3] {
+        [
+            ("ENCODING", self.encoding.as_deref()),
+            ("LC_COLLATE", self.lc_collate.as_deref()),
+            ("LC_CTYPE", self.lc_ctype.as_deref()),
+        ]
+    }
+
+    /// Clauses to add to `CREATE DATABASE`. Only `template0` can be copied with another encoding
+    /// or locale than the server's, so it is used whenever any of them is set
+    fn clauses(&self) -> String {
+        let mut clauses: String = self
+            .settings()
+            .into_iter()
+            .filter_map(|(setting, value)| Some(format!(" {setting} '{}'", value?)))
+            .collect();
+
+        if !clauses.is_empty() {
+            clauses.push_str(" TEMPLATE template0");
+        }
+
+        clauses
+    }
+}
+
+/// Make sure every setting of `locale` is one the server knows. The settings end up in a
+/// statement, so only names made up of letters, numbers and `_.@-` are looked up at all
+#[tracing::instrument(skip(pool))]
+pub async fn check_db_locale(pool: &PgPool, locale: &DbLocale) -> Result<(), Error> {
+    for (setting, value) in locale.settings() {
+        let value = match value {
+            Some(value) => value,
+            None => continue,
+        };
+
+        let is_valid = !value.is_empty()
+            && value
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || b"_.@-".contains(&byte));
+        let is_known = is_valid
+            && if setting == "ENCODING" {
+                sqlx::query_scalar("SELECT pg_char_to_encoding($1) >= 0")
+                    .bind(value)
+                    .fetch_one(pool)
+                    .await?
+            } else {
+                sqlx::query_scalar(
+                    "SELECT EXISTS (SELECT 1 FROM pg_collation WHERE collcollate = $1)",
+                )
+                .bind(value)
+                .fetch_one(pool)
+                .await?
+            };
+
+        if !is_known {
+            return Err(Error::UnknownLocale {
+                setting: setting.to_lowercase(),
+                value: value.to_string(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// What [shared_role] did to get a role with a new password
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoleOutcome {
+    Created,
+    PasswordCycled,
+}
+
+/// Whether [shared_db] had to create the database
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DbOutcome {
+    Created,
+    Existing,
+}
+
+/// Make sure the role `username` of `project_name` exists, giving it a new password and
+/// `connection_limit`. Returns the password, and whether the role had to be created
+#[tracing::instrument(skip(pool))]
+pub async fn shared_role(
+    pool: &PgPool,
+    project_name: &str,
+    username: &str,
+    connection_limit: Option<u32>,
+) -> Result<(String, RoleOutcome), Error> {
+    let password = generate_password();
+    // Postgres takes -1 to mean no limit
+    let connection_limit = connection_limit.map_or(-1, i64::from);
+
+    let matching_user = sqlx::query("SELECT rolname FROM pg_roles WHERE rolname = $1")
+        .bind(&username)
+        .fetch_optional(pool)
+        .await?;
+
+    if matching_user.is_none() {
+        info!("creating new user");
+
+        // Binding does not work for identifiers
+        // https://stackoverflow.com/questions/63723236/sql-statement-to-create-role-fails-on-postgres-12-using-dapper
+        let create_role_query = format!(
+            "CREATE ROLE \"{username}\" WITH LOGIN PASSWORD '{password}' CONNECTION LIMIT {connection_limit}"
+        );
+        sqlx::query(&create_role_query)
+            .execute(pool)
+            .await
+            .map_err(|e| {
+                statement_error(e, |reason| Error::CreateRole {
+                    project: project_name.to_string(),
+                    role: username.to_string(),
+                    reason,
+                })
+            })?;
+    } else {
+        info!("cycling password of user");
+
+        // Binding does not work for identifiers
+        // https://stackoverflow.com/questions/63723236/sql-statement-to-create-role-fails-on-postgres-12-using-dapper
+        let update_role_query = format!(
+            "ALTER ROLE \"{username}\" WITH LOGIN PASSWORD '{password}' CONNECTION LIMIT {connection_limit}"
+        );
+        sqlx::query(&update_role_query)
+            .execute(pool)
+            .await
+            .map_err(|e| {
+                statement_error(e, |reason| Error::UpdateRole {
+                    project: project_name.to_string(),
+                    role: username.to_string(),
+                    reason,
+                })
+            })?;
+    }
+
+    let outcome = if matching_user.is_none() {
+        RoleOutcome::Created
+    } else {
+        RoleOutcome::PasswordCycled
+    };
+
+    Ok((password, outcome))
+}
+
+/// Give the existing role `username` of `project_name` a new password without changing anything
+/// else about it. Returns the password
+#[tracing::instrument(skip(pool))]
+pub async fn rotate_role_password(
+    pool: &PgPool,
+    project_name: &str,
+    username: &str,
+) -> Result<String, Error> {
+    let password = generate_password();
+
+    // Binding does not work for identifiers
+    // https://stackoverflow.com/questions/63723236/sql-statement-to-create-role-fails-on-postgres-12-using-dapper
+    let rotate_query = format!("ALTER ROLE \"{username}\" WITH PASSWORD '{password}'");
+    sqlx::query(&rotate_query)
+        .execute(pool)
+        .await
+        .map_err(|e| {
+            statement_error(e, |reason| Error::UpdateRole {
+                project: project_name.to_string(),
+                role: username.to_string(),
+                reason,
+            })
+        })?;
+
+    Ok(password)
+}
+
+/// Drop the role `username`, if it exists
+#[tracing::instrument(skip(pool))]
+pub async fn drop_shared_role(pool: &PgPool, username: &str) -> Result<(), Error> {
+    // Binding does not work for identifiers
+    // https://stackoverflow.com/questions/63723236/sql-statement-to-create-role-fails-on-postgres-12-using-dapper
+    let drop_role_query = format!("DROP ROLE IF EXISTS \"{username}\"");
+    sqlx::query(&drop_role_query)
+        .execute(pool)
+        .await
+        .map_err(|e| statement_error(e, Error::DeleteRole))?;
+
+    Ok(())
+}
+
+/// Whether the database `database_name` belongs to the role `username`, by being owned by it or,
+/// in least privilege mode, by having been granted to it. `None` when the database does not exist
+#[tracing::instrument(skip(pool))]
+pub async fn db_belongs_to(
+    pool: &PgPool,
+    database_name: &str,
+    username: &str,
+) -> Result<Option<bool>, Error> {
+    // PUBLIC can connect to every database by default, but only owners and grants give CREATE
+    let belongs: Option<Option<bool>> = sqlx::query_scalar(
+        "SELECT has_database_privilege(r.oid, d.oid, 'CREATE') FROM pg_database d LEFT JOIN pg_roles r ON r.rolname = $2 WHERE d.datname = $1",
+    )
+    .bind(database_name)
+    .bind(username)
+    .fetch_optional(pool)
+    .await?;
+
+    // A missing role gives NULL, and a missing role owns nothing
+    Ok(belongs.map(|belongs| belongs.unwrap_or(false)))
+}
+
+/// Drop the database `database_name` if it exists, disconnecting everyone who is still using it
+#[tracing::instrument(skip(pool))]
+pub async fn drop_shared_db(pool: &PgPool, database_name: &str) -> Result<(), Error> {
+    let matching_db = sqlx::query("SELECT datname FROM pg_database WHERE datname = $1")
+        .bind(&database_name)
+        .fetch_optional(pool)
+        .await?;
+
+    if matching_db.is_none() {
+        return Ok(());
+    }
+
+    info!("deleting database");
+
+    // Stop new connections from coming in while the existing ones are terminated
+    let revoke_query = format!("ALTER DATABASE \"{database_name}\" ALLOW_CONNECTIONS false");
+    sqlx::query(&revoke_query)
+        .execute(pool)
+        .await
+        .map_err(|e| statement_error(e, Error::DeleteDB))?;
+
+    sqlx::query(
+        "SELECT pg_terminate_backend(pid) FROM pg_stat_activity WHERE datname = $1 AND pid <> pg_backend_pid()",
+    )
+    .bind(&database_name)
+    .execute(pool)
+    .await
+    .map_err(|e| statement_error(e, Error::DeleteDB))?;
+
+    // Binding does not work for identifiers
+    // https://stackoverflow.com/questions/63723236/sql-statement-to-create-role-fails-on-postgres-12-using-dapper
+    let drop_db_query = format!("DROP DATABASE IF EXISTS \"{database_name}\"");
+    sqlx::query(&drop_db_query)
+        .execute(pool)
+        .await
+        .map_err(|e| statement_error(e, Error::DeleteDB))?;
+
+    Ok(())
+}
+
+/// Make sure the database of `project_name` exists, returning whether it had to be created. New
+/// databases are owned by `owner`, or by the role of `pool` when it is not set, and get `locale`,
+/// which has to have passed [check_db_locale]
+#[tracing::instrument(skip(pool))]
+pub async fn shared_db(
+    pool: &PgPool,
+    project_name: &str,
+    database_name: &str,
+    owner: Option<&str>,
+    locale: &DbLocale,
+) -> Result<DbOutcome, Error> {
+    let matching_db = sqlx::query("SELECT datname FROM pg_database WHERE datname = $1")
+        .bind(&database_name)
+        .fetch_optional(pool)
+        .await?;
+
+    if matching_db.is_none() {
+        info!("creating database");
+
+        // Binding does not work for identifiers
+        // https://stackoverflow.com/questions/63723236/sql-statement-to-create-role-fails-on-postgres-12-using-dapper
+        let create_db_query = match owner {
+            Some(owner) => format!("CREATE DATABASE \"{database_name}\" OWNER '{owner}'"),
+            None => format!("CREATE DATABASE \"{database_name}\""),
+        } + &locale.clauses();
+        sqlx::query(&create_db_query)
+            .execute(pool)
+            .await
+            .map_err(|e| {
+                statement_error(e, |reason| Error::CreateDB {
+                    project: project_name.to_string(),
+                    database: database_name.to_string(),
+                    reason,
+                })
+            })?;
+    }
+
+    if matching_db.is_none() {
+        Ok(DbOutcome::Created)
+    } else {
+        Ok(DbOutcome::Existing)
+    }
+}
+
+/// Give `username` full use of a database it does not own. Schema privileges live inside the
+/// database, so `connect_options` are used to connect to it
+#[tracing::instrument(skip(pool, connect_options))]
+pub async fn grant_shared_db(
+    pool: &PgPool,
+    connect_options: &PgConnectOptions,
+    project_name: &str,
+    database_name: &str,
+    username: &str,
+) -> Result<(), Error> {
+    let grant_db_query =
+        format!("GRANT ALL PRIVILEGES ON DATABASE \"{database_name}\" TO \"{username}\"");
+    sqlx::query(&grant_db_query)
+        .execute(pool)
+        .await
+        .map_err(|e| {
+            statement_error(e, |reason| Error::CreateDB {
+                project: project_name.to_string(),
+                database: database_name.to_string(),
+                reason,
+            })
+        })?;
+
+    let mut conn = connect_options
+        .clone()
+        .database(database_name)
+        .connect()
+        .await?;
+
+    let grant_schema_query = format!("GRANT ALL ON SCHEMA public TO \"{username}\"");
+    sqlx::query(&grant_schema_query)
+        .execute(&mut conn)
+        .await
+        .map_err(|e| {
+            statement_error(e, |reason| Error::CreateDB {
+                project: project_name.to_string(),
+                database: database_name.to_string(),
+                reason,
+            })
+        })?;
+
+    conn.close().await?;
+
+    Ok(())
+}
+
+/// What a role besides the project's own can do in a shared database
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Privilege {
+    /// Everything the project's role can do, by being a member of it
+    Owner,
+    /// Read and write the rows of every table, but not change the tables
+    ReadWrite,
+    /// Only read the rows of every table
+    ReadOnly,
+}
+
+/// Give `role` exactly `privilege` on everything in `schemas` of `database_name`, including
+/// tables `owner` creates there later. What the role had before is taken away first, so a role
+/// can move between privileges. Default privileges only cover tables created by the role they are
+/// set for, which is the project's own role
+#[tracing::instrument(skip(pool, connect_options))]
+pub async fn grant_shared_role(
+    pool: &PgPool,
+    connect_options: &PgConnectOptions,
+    database_name: &str,
+    owner: &str,
+    role: &str,
+    privilege: Privilege,
+    schemas: &[&str],
+) -> Result<(), Error> {
+    // Binding does not work for identifiers
+    // https://stackoverflow.com/questions/63723236/sql-statement-to-create-role-fails-on-postgres-12-using-dapper
+    let membership_query = if privilege == Privilege::Owner {
+        format!("GRANT \"{owner}\" TO \"{role}\"")
+    } else {
+        format!("REVOKE \"{owner}\" FROM \"{role}\"")
+    };
+    let grant_connect_query =
+        format!("GRANT CONNECT ON DATABASE \"{database_name}\" TO \"{role}\"");
+    for query in [membership_query, grant_connect_query] {
+        sqlx::query(&query)
+            .execute(pool)
+            .await
+            .map_err(|e| statement_error(e, Error::GrantRole))?;
+    }
+
+    // Schema privileges live inside the database, so they need a connection to it
+    let mut conn = connect_options
+        .clone()
+        .database(database_name)
+        .connect()
+        .await?;
+
+    let (table_privileges, sequence_privileges) = match privilege {
+        Privilege::Owner => (None, None),
+        Privilege::ReadWrite => (
+            Some("SELECT, INSERT, UPDATE, DELETE"),
+            Some("USAGE, SELECT"),
+        ),
+        Privilege::ReadOnly => (Some("SELECT"), None),
+    };
+
+    for schema in schemas {
+        let default_privileges =
+            format!("ALTER DEFAULT PRIVILEGES FOR ROLE \"{owner}\" IN SCHEMA \"{schema}\"");
+        let mut queries = vec![
+            format!("GRANT USAGE ON SCHEMA \"{schema}\" TO \"{role}\""),
+            format!("REVOKE ALL ON ALL TABLES IN SCHEMA \"{schema}\" FROM \"{role}\""),
+            format!("REVOKE ALL ON ALL SEQUENCES IN SCHEMA \"{schema}\" FROM \"{role}\""),
+            format!("{default_privileges} REVOKE ALL ON TABLES FROM \"{role}\""),
+            format!("{default_privileges} REVOKE ALL ON SEQUENCES FROM \"{role}\""),
+        ];
+        if let Some(privileges) = table_privileges {
+            queries.push(format!(
+                "GRANT {privileges} ON ALL TABLES IN SCHEMA \"{schema}\" TO \"{role}\""
+            ));
+            queries.push(format!(
+                "{default_privileges} GRANT {privileges} ON TABLES TO \"{role}\""
+            ));
+        }
+        if let Some(privileges) = sequence_privileges {
+            queries.push(format!(
+                "GRANT {privileges} ON ALL SEQUENCES IN SCHEMA \"{schema}\" TO \"{role}\""
+            ));
+            queries.push(format!(
+                "{default_privileges} GRANT {privileges} ON SEQUENCES TO \"{role}\""
+            ));
+        }
+
+        for query in &queries {
+            sqlx::query(query)
+                .execute(&mut conn)
+                .await
+                .map_err(|e| statement_error(e, Error::GrantRole))?;
+        }
+    }
+
+    conn.close().await?;
+
+    Ok(())
+}
+
+/// Create `extensions` in `database_name`. Most extensions need a superuser, so this connects to
+/// the database with the provisioner's own `connect_options`
+#[tracing::instrument(skip(connect_options))]
+pub async fn shared_extensions(
+    connect_options: &PgConnectOptions,
+    database_name: &str,
+    extensions: &[String],
+) -> Result<(), Error> {
+    let mut conn = connect_options
+        .clone()
+        .database(database_name)
+        .connect()
+        .await?;
+
+    for extension in extensions {
+        info!(%extension, "creating extension");
+
+        // Binding does not work for identifiers
+        // https://stackoverflow.com/questions/63723236/sql-statement-to-create-role-fails-on-postgres-12-using-dapper
+        let create_extension_query = format!("CREATE EXTENSION IF NOT EXISTS \"{extension}\"");
+        sqlx::query(&create_extension_query)
+            .execute(&mut conn)
+            .await
+            .map_err(|e| {
+                statement_error(e, |reason| Error::CreateExtension {
+                    database: database_name.to_string(),
+                    extension: extension.to_string(),
+                    reason,
+                })
+            })?;
+    }
+
+    conn.close().await?;
+
+    Ok(())
+}
+
+/// Make sure `database_name` has a schema called `schema` owned by `username`, and make it the
+/// default `search_path` of the role in that database
+#[tracing::instrument(skip(pool, connect_options))]
+pub async fn shared_schema(
+    pool: &PgPool,
+    connect_options: &PgConnectOptions,
+    database_name: &str,
+    username: &str,
+    schema: &str,
+) -> Result<(), Error> {
+    // Schemas live inside the database, so they need a connection to it
+    let mut conn = connect_options
+        .clone()
+        .database(database_name)
+        .connect()
+        .await?;
+
+    let create_schema_query =
+        format!("CREATE SCHEMA IF NOT EXISTS \"{schema}\" AUTHORIZATION \"{username}\"");
+    sqlx::query(&create_schema_query)
+        .execute(&mut conn)
+        .await
+        .map_err(|e| statement_error(e, Error::CreateSchema))?;
+
+    conn.close().await?;
+
+    let search_path_query = format!(
+        "ALTER ROLE \"{username}\" IN DATABASE \"{database_name}\" SET search_path TO \"{schema}\""
+    );
+    sqlx::query(&search_path_query)
+        .execute(pool)
+        .await
+        .map_err(|e| statement_error(e, Error::CreateSchema))?;
+
+    Ok(())
+}
+
+/// Describe a failed statement with `describe`, unless it failed because the shared Postgres is
+/// read-only. That is not a bug, and goes away once requests reach the primary again
+fn statement_error(error: sqlx::Error, describe: impl FnOnce(String) -> Error) -> Error {
+    let is_read_only = error
+        .as_database_error()
+        .and_then(|error| error.code())
+        .map_or(false, |code| code == READ_ONLY_SQL_TRANSACTION);
+
+    if is_read_only {
+        Error::DatabaseReadOnly(error.to_string())
+    } else {
+        describe(error.to_string())
+    }
+}
@@ -0,0 +1,162 @@
+use std::{convert::Infallible, fmt::Display, str::FromStr};
+
+/// Status of an RDS instance as reported by AWS
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InstanceStatus {
+    Available,
+    BackingUp,
+    Creating,
+    Deleting,
+    Failed,
+    IncompatibleNetwork,
+    IncompatibleOptionGroup,
+    IncompatibleParameters,
+    IncompatibleRestore,
+    Maintenance,
+    Modifying,
+    Rebooting,
+    ResettingMasterCredentials,
+    RestoreError,
+    Starting,
+    Stopped,
+    Stopping,
+    StorageFull,
+    StorageOptimization,
+    Upgrading,
+    /// Any status not known to the provisioner
+    Unknown(String),
+}
+
+impl InstanceStatus {
+    /// The status as reported by AWS
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Available => "available",
+            Self::BackingUp => "backing-up",
+            Self::Creating => "creating",
+            Self::Deleting => "deleting",
+            Self::Failed => "failed",
+            Self::IncompatibleNetwork => "incompatible-network",
+            Self::IncompatibleOptionGroup => "incompatible-option-group",
+            Self::IncompatibleParameters => "incompatible-parameters",
+            Self::IncompatibleRestore => "incompatible-restore",
+            Self::Maintenance => "maintenance",
+            Self::Modifying => "modifying",
+            Self::Rebooting => "rebooting",
+            Self::ResettingMasterCredentials => "resetting-master-credentials",
+            Self::RestoreError => "restore-error",
+            Self::Starting => "starting",
+            Self::Stopped => "stopped",
+            Self::Stopping => "stopping",
+            Self::StorageFull => "storage-full",
+            Self::StorageOptimization => "storage-optimization",
+            Self::Upgrading => "upgrading",
+            Self::Unknown(status) => status,
+        }
+    }
+
+    /// Check if the instance is stuck in a state it will not recover from by itself
+    pub fn is_failed(&self) -> bool {
+        matches!(
+            self,
+            Self::Failed
+                | Self::IncompatibleNetwork
+                | Self::IncompatibleOptionGroup
+                | Self::IncompatibleParameters
+                | Self::IncompatibleRestore
+                | Self::RestoreError
+        )
+    }
+
+    /// Check if an instance in this status counts as being in `wanted`. Instances can be used
+    /// as normal while their storage is optimized after it was increased
+    pub fn reached(&self, wanted: &InstanceStatus) -> bool {
+        self == wanted || (*wanted == Self::Available && *self == Self::StorageOptimization)
+    }
+}
+
+impl From<&str> for InstanceStatus {
+    fn from(status: &str) -> Self {
+        match status {
+            "available" => Self::Available,
+            "backing-up" => Self::BackingUp,
+            "creating" => Self::Creating,
+            "deleting" => Self::Deleting,
+            "failed" => Self::Failed,
+            "incompatible-network" => Self::IncompatibleNetwork,
+            "incompatible-option-group" => Self::IncompatibleOptionGroup,
+            "incompatible-parameters" => Self::IncompatibleParameters,
+            "incompatible-restore" => Self::IncompatibleRestore,
+            "maintenance" => Self::Maintenance,
+            "modifying" => Self::Modifying,
+            "rebooting" => Self::Rebooting,
+            "resetting-master-credentials" => Self::ResettingMasterCredentials,
+            "restore-error" => Self::RestoreError,
+            "starting" => Self::Starting,
+            "stopped" => Self::Stopped,
+            "stopping" => Self::Stopping,
+            "storage-full" => Self::StorageFull,
+            "storage-optimization" => Self::StorageOptimization,
+            "upgrading" => Self::Upgrading,
+            other => Self::Unknown(other.to_string()),
+        }
+    }
+}
+
+impl FromStr for InstanceStatus {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.into())
+    }
+}
+
+impl Display for InstanceStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for status in [
+            "available",
+            "creating",
+            "resetting-master-credentials",
+            "incompatible-network",
+            "storage-full",
+            "storage-optimization",
+            "some-new-status",
+        ] {
+            assert_eq!(InstanceStatus::from(status).as_str(), status);
+        }
+
+        assert_eq!(
+            InstanceStatus::from("some-new-status"),
+            InstanceStatus::Unknown("some-new-status".to_string())
+        );
+    }
+
+    #[test]
+    fn failed() {
+        for status in ["failed", "incompatible-parameters", "restore-error"] {
+            assert!(InstanceStatus::from(status).is_failed(), "{status}");
+        }
+
+        for status in ["available", "creating", "modifying", "some-new-status"] {
+            assert!(!InstanceStatus::from(status).is_failed(), "{status}");
+        }
+    }
+
+    #[test]
+    fn reached() {
+        assert!(InstanceStatus::Available.reached(&InstanceStatus::Available));
+        assert!(InstanceStatus::StorageOptimization.reached(&InstanceStatus::Available));
+        assert!(!InstanceStatus::StorageOptimization.reached(&InstanceStatus::Modifying));
+        assert!(!InstanceStatus::StorageFull.reached(&InstanceStatus::Available));
+    }
+}
@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use hyper::{client::HttpConnector, header::CONTENT_TYPE, Body, Client, Method, Request, Uri};
+use hyper_tls::HttpsConnector;
+use shuttle_proto::provisioner::ProvisionSummary;
+use tracing::{debug, warn};
+
+use crate::Error;
+
+/// Longest a webhook delivery can take before it is given up on
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Tells another service about every provision which finished, by POSTing its summary as JSON.
+/// The summary holds no secrets
+pub struct Webhook {
+    url: Uri,
+    client: Client<HttpsConnector<HttpConnector>>,
+}
+
+impl Webhook {
+    pub fn new(url: &str) -> Result<Self, Error> {
+        let url = url.parse().map_err(|error| {
+            Error::InvalidConfig(format!("invalid webhook URL '{url}': {error}"))
+        })?;
+
+        Ok(Self {
+            url,
+            client: Client::builder().build(HttpsConnector::new()),
+        })
+    }
+
+    /// Deliver `summary` in the background. Delivery is best-effort, so failures are only logged
+    pub fn notify(&self, summary: &ProvisionSummary) {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(self.url.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(summary.to_json()))
+            .expect("webhook request to be valid");
+        let delivery = self.client.request(request);
+
+        tokio::spawn(async move {
+            match tokio::time::timeout(WEBHOOK_TIMEOUT, delivery).await {
+                Ok(Ok(response)) if response.status().is_success() => {
+                    debug!("delivered provision webhook")
+                }
+                Ok(Ok(response)) => {
+                    warn!(status = %response.status(), "provision webhook was rejected")
+                }
+                Ok(Err(error)) => warn!(
+                    error = &error as &dyn std::error::Error,
+                    "failed to deliver provision webhook"
+                ),
+                Err(_) => warn!(timeout = ?WEBHOOK_TIMEOUT, "provision webhook timed out"),
+            }
+        });
+    }
+}